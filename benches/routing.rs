@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use gateway_rs::router::{DevAddrFilter, EuiFilter, Routing, RoutingIndex};
+use helium_proto::{routing_information::Data as RoutingData, Eui, RoutingInformation};
+
+/// Constructs routings for the given number of OUIs, each with a handful of
+/// EUIs and two devaddr subnets, similar to the routing on chain.
+fn routings(ouis: u32) -> Vec<Routing> {
+    (0..ouis)
+        .map(|oui| {
+            let pairs: Vec<(u64, u64)> = (0..10)
+                .map(|n| (u64::from(oui) * 100 + n, u64::from(oui)))
+                .collect();
+            let filters = EuiFilter::from_pairs(&pairs).into_iter().collect();
+            let subnets = (0..2)
+                .map(|n| DevAddrFilter::new((oui * 2 + n) * 8, 8).expect("subnet"))
+                .collect();
+            Routing::from_filters(oui, filters, subnets)
+        })
+        .collect()
+}
+
+fn scan(routings: &[Routing], info: &Option<RoutingInformation>) -> Vec<usize> {
+    routings
+        .iter()
+        .enumerate()
+        .filter(|(_, routing)| routing.matches_routing_info(info))
+        .map(|(key, _)| key)
+        .collect()
+}
+
+fn bench_routing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("routing");
+    for ouis in [10, 100, 500] {
+        let routings = routings(ouis);
+        let index = RoutingIndex::new(routings.iter().enumerate());
+        let devaddr = Some(RoutingInformation {
+            data: Some(RoutingData::Devaddr(0x48000000 | (ouis - 1) * 16)),
+        });
+        let eui = Some(RoutingInformation {
+            data: Some(RoutingData::Eui(Eui {
+                deveui: u64::from(ouis - 1) * 100,
+                appeui: u64::from(ouis - 1),
+            })),
+        });
+        group.bench_with_input(
+            BenchmarkId::new("devaddr_scan", ouis),
+            &devaddr,
+            |b, info| b.iter(|| scan(&routings, black_box(info))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("devaddr_index", ouis),
+            &devaddr,
+            |b, info| b.iter(|| index.matches(black_box(info))),
+        );
+        group.bench_with_input(BenchmarkId::new("eui_scan", ouis), &eui, |b, info| {
+            b.iter(|| scan(&routings, black_box(info)))
+        });
+        group.bench_with_input(BenchmarkId::new("eui_index", ouis), &eui, |b, info| {
+            b.iter(|| index.matches(black_box(info)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_routing);
+criterion_main!(benches);
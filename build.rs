@@ -0,0 +1,4 @@
+fn main() -> std::io::Result<()> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    tonic_build::configure().compile(&["proto/local.proto"], &["proto"])
+}
@@ -26,6 +26,12 @@ pub fn subnet_from_devaddr(devaddr: u32, netid_list: &[u32]) -> u32 {
     lower + nwk_addr(devaddr)
 }
 
+/// Returns the NetID encoded in a LoRaWAN devaddr.
+///
+pub fn netid_from_devaddr(devaddr: u32) -> u32 {
+    parse_netid(devaddr)
+}
+
 //
 // Internal functions
 //
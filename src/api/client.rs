@@ -1,24 +1,44 @@
 use super::{
-    connect_uri, AddGatewayReq, ConfigReq, ConfigValue, GatewayStakingMode, HeightReq, HeightRes,
-    PubkeyReq, RegionReq, SignReq,
+    connect_uri, proto::Client, AccountingReq, AddGatewayReq, ApiAddress, ConfigReq, ConfigValue,
+    GatewayStakingMode, HeightReq, HeightRes, PubkeyReq, RegionReq, ReloadReq, ReloadRes,
+    RouterRes, RoutersReq, SignReq, StatusReq, StatusRes, TapReq, TapRes, TestTransmitReq,
+    TrafficCountersRes,
 };
 use crate::{error::Error, settings::StakingMode, PublicKey, Region, Result, TxnEnvelope};
-use helium_proto::{services::local::Client, BlockchainTxnAddGatewayV1};
+use helium_proto::BlockchainTxnAddGatewayV1;
+use http::Uri;
 use std::convert::TryFrom;
-use tonic::transport::{Channel, Endpoint};
+use tokio::net::UnixStream;
+use tonic::{
+    transport::{Channel, Endpoint},
+    Streaming,
+};
 
 pub struct LocalClient {
     client: Client<Channel>,
 }
 
 impl LocalClient {
-    pub async fn new(port: u16) -> Result<Self> {
-        let uri = connect_uri(port);
-        let endpoint = Endpoint::from_shared(uri).unwrap();
-        let client = Client::connect(endpoint)
-            .await
-            .map_err(Error::local_client_connect)?;
-        Ok(Self { client })
+    pub async fn new(address: &ApiAddress) -> Result<Self> {
+        let channel = match address {
+            ApiAddress::Tcp(port) => {
+                let uri = connect_uri(*port);
+                Endpoint::from_shared(uri).unwrap().connect().await
+            }
+            ApiAddress::Unix(path) => {
+                let path = path.clone();
+                // The uri is required but not used to connect to a socket
+                Endpoint::from_static("http://localhost")
+                    .connect_with_connector(tower::service_fn(move |_: Uri| {
+                        UnixStream::connect(path.clone())
+                    }))
+                    .await
+            }
+        }
+        .map_err(Error::local_client_connect)?;
+        Ok(Self {
+            client: Client::new(channel),
+        })
     }
 
     pub async fn pubkey(&mut self) -> Result<(PublicKey, PublicKey)> {
@@ -54,6 +74,40 @@ impl LocalClient {
         Region::from_i32(response.into_inner().region)
     }
 
+    pub async fn routers(&mut self) -> Result<Vec<RouterRes>> {
+        let response = self.client.routers(RoutersReq {}).await?.into_inner();
+        Ok(response.routers)
+    }
+
+    pub async fn accounting(&mut self) -> Result<Vec<TrafficCountersRes>> {
+        let response = self.client.accounting(AccountingReq {}).await?.into_inner();
+        Ok(response.entries)
+    }
+
+    pub async fn status(&mut self) -> Result<StatusRes> {
+        let response = self.client.status(StatusReq {}).await?.into_inner();
+        Ok(response)
+    }
+
+    /// Streams the records of packets and events as they happen
+    pub async fn tap(&mut self, request: TapReq) -> Result<Streaming<TapRes>> {
+        let response = self.client.tap(request).await?.into_inner();
+        Ok(response)
+    }
+
+    /// Transmits a test packet and returns the tx power used
+    pub async fn test_transmit(&mut self, request: TestTransmitReq) -> Result<u32> {
+        let response = self.client.test_transmit(request).await?.into_inner();
+        Ok(response.power)
+    }
+
+    /// Reloads the settings of the running service and returns the names of
+    /// the changed settings
+    pub async fn reload(&mut self) -> Result<ReloadRes> {
+        let response = self.client.reload(ReloadReq {}).await?.into_inner();
+        Ok(response)
+    }
+
     pub async fn add_gateway(
         &mut self,
         owner: &PublicKey,
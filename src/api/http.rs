@@ -0,0 +1,206 @@
+//! An optional HTTP listener exposing local API operations as JSON endpoints
+//! for clients that can't speak gRPC. Requests are answered by the same
+//! `LocalServer` that serves gRPC requests.
+
+use super::{
+    json, listen_addr, proto::Api, AddGatewayReq, ConfigReq, GatewayStakingMode, HeightReq,
+    LocalServer, PubkeyReq, RegionReq, StatusReq,
+};
+use crate::{settings::StakingMode, Base64, Error, PublicKey, Region, Result};
+use futures::TryFutureExt;
+use hyper::{
+    body,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    http::request::Parts,
+    service::{make_service_fn, service_fn},
+    Body, Method, Server, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use slog::{info, Logger};
+use std::{convert::Infallible, net::SocketAddr, str::FromStr, sync::Arc};
+use tonic::{Code, Request, Status};
+
+type HttpResult = std::result::Result<Value, Status>;
+
+#[derive(Debug, Deserialize)]
+struct ConfigQuery {
+    /// Comma separated chain variable names
+    keys: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddGatewayBody {
+    owner: String,
+    payer: String,
+    #[serde(default = "default_staking_mode")]
+    mode: String,
+}
+
+fn default_staking_mode() -> String {
+    StakingMode::DataOnly.to_string()
+}
+
+pub async fn serve(
+    api: Arc<LocalServer>,
+    port: u16,
+    shutdown: triggered::Listener,
+    logger: &Logger,
+) -> Result {
+    let addr: SocketAddr = listen_addr(port).parse()?;
+    info!(logger, "starting http"; "listen" => addr.to_string());
+    let make_service = make_service_fn(move |_conn| {
+        let api = api.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(api.clone(), request))) }
+    });
+    Server::try_bind(&addr)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .map_err(Error::from)
+        .await
+}
+
+/// Passes the authorization header of the HTTP request on as the metadata the
+/// signing policy checks
+fn api_request<T>(parts: &Parts, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(value) = parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+    {
+        request.metadata_mut().insert("authorization", value);
+    }
+    request
+}
+
+async fn handle(
+    api: Arc<LocalServer>,
+    request: hyper::Request<Body>,
+) -> std::result::Result<hyper::Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let result = match (&parts.method, parts.uri.path()) {
+        (&Method::GET, "/pubkey") => pubkey(&api).await,
+        (&Method::GET, "/region") => region(&api).await,
+        (&Method::GET, "/height") => height(&api).await,
+        (&Method::GET, "/config") => config(&api, parts.uri.query()).await,
+        (&Method::GET, "/status") => status(&api).await,
+        (&Method::POST, "/add_gateway") => add_gateway(&api, &parts, body).await,
+        _ => Err(Status::not_found("unknown endpoint")),
+    };
+    let (code, value) = match result {
+        Ok(value) => (StatusCode::OK, value),
+        Err(status) => (
+            status_code(status.code()),
+            json!({ "error": status.message() }),
+        ),
+    };
+    let response = hyper::Response::builder()
+        .status(code)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .expect("valid response");
+    Ok(response)
+}
+
+fn status_code(code: Code) -> StatusCode {
+    match code {
+        Code::InvalidArgument => StatusCode::BAD_REQUEST,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn internal(err: Error) -> Status {
+    Status::internal(format!("{err}"))
+}
+
+async fn pubkey(api: &LocalServer) -> HttpResult {
+    let reply = api.pubkey(Request::new(PubkeyReq {})).await?.into_inner();
+    let key = PublicKey::from_bytes(reply.address).map_err(|err| internal(err.into()))?;
+    let onboarding =
+        PublicKey::from_bytes(reply.onboarding_address).map_err(|err| internal(err.into()))?;
+    Ok(json!({
+        "key": key.to_string(),
+        "onboarding": onboarding.to_string(),
+    }))
+}
+
+async fn region(api: &LocalServer) -> HttpResult {
+    let reply = api.region(Request::new(RegionReq {})).await?.into_inner();
+    let region = Region::from_i32(reply.region).map_err(internal)?;
+    Ok(json!({ "region": region.to_string() }))
+}
+
+async fn height(api: &LocalServer) -> HttpResult {
+    let reply = api.height(Request::new(HeightReq {})).await?.into_inner();
+    json::height(reply).map_err(internal)
+}
+
+async fn config(api: &LocalServer, query: Option<&str>) -> HttpResult {
+    let query: ConfigQuery = serde_urlencoded::from_str(query.unwrap_or_default())
+        .map_err(|_| Status::invalid_argument("Missing keys"))?;
+    let keys = query
+        .keys
+        .split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect();
+    let reply = api
+        .config(Request::new(ConfigReq { keys }))
+        .await?
+        .into_inner();
+    let values: Vec<Value> = reply
+        .values
+        .into_iter()
+        .map(|value| {
+            json!({
+                "name": value.name,
+                "type": value.r#type,
+                "value": String::from_utf8_lossy(&value.value),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "values": values,
+        "height": reply.height,
+        "stale": reply.stale,
+    }))
+}
+
+async fn status(api: &LocalServer) -> HttpResult {
+    let reply = api.status(Request::new(StatusReq {})).await?.into_inner();
+    json::status(reply).map_err(internal)
+}
+
+async fn add_gateway(api: &LocalServer, parts: &Parts, body: Body) -> HttpResult {
+    let body = body::to_bytes(body)
+        .await
+        .map_err(|_| Status::invalid_argument("Invalid request body"))?;
+    let body: AddGatewayBody = serde_json::from_slice(&body)
+        .map_err(|_| Status::invalid_argument("Invalid request body"))?;
+    let owner = PublicKey::from_str(&body.owner)
+        .map_err(|_| Status::invalid_argument("Invalid owner address"))?;
+    let payer = PublicKey::from_str(&body.payer)
+        .map_err(|_| Status::invalid_argument("Invalid payer address"))?;
+    let mode = StakingMode::from_str(&body.mode)
+        .map_err(|_| Status::invalid_argument("Invalid staking mode"))?;
+    let reply = api
+        .add_gateway(api_request(
+            parts,
+            AddGatewayReq {
+                owner: owner.to_vec(),
+                payer: payer.to_vec(),
+                staking_mode: GatewayStakingMode::from(&mode).into(),
+            },
+        ))
+        .await?
+        .into_inner();
+    Ok(json!({
+        "mode": mode.to_string(),
+        "txn": reply.add_gateway_txn.to_b64(),
+    }))
+}
@@ -0,0 +1,89 @@
+//! JSON representations of local API responses, shared by the `info` command
+//! and the HTTP listener.
+
+use super::{HeightRes, RouterRes, StatusRes};
+use crate::{service::gateway::GatewayVersion, Error, KeyedUri, Region, Result};
+use serde_json::{json, Value};
+
+fn keyed_uri(uri: KeyedUri) -> Value {
+    json!({
+        "uri": uri.uri.to_string(),
+        "key": uri.pubkey.to_string(),
+    })
+}
+
+pub fn height(height: HeightRes) -> Result<Value> {
+    let gateway = height
+        .gateway
+        .ok_or_else(|| Error::custom("No uri for gateway"))
+        .and_then(KeyedUri::try_from)?;
+    Ok(json!({
+        "uri": gateway.uri.to_string(),
+        "key": gateway.pubkey.to_string(),
+        "height": height.height,
+        "block_age": height.block_age,
+        "version": Some(GatewayVersion::from(height.gateway_version)),
+    }))
+}
+
+pub fn routers(routers: Vec<RouterRes>) -> Result<Value> {
+    let mut entries = vec![];
+    for router in routers {
+        let uri = router
+            .uri
+            .ok_or_else(|| Error::custom("No uri for router"))
+            .and_then(KeyedUri::try_from)?;
+        entries.push(json!({
+            "oui": router.oui,
+            "uri": uri.uri.to_string(),
+            "key": uri.pubkey.to_string(),
+            "filters": router.filters,
+            "subnets": router.subnets,
+            "queued": router.queued,
+            "running": router.running,
+            "last_send": router.last_send,
+            "last_send_error": router.last_send_error,
+        }));
+    }
+    Ok(json!(entries))
+}
+
+pub fn status(status: StatusRes) -> Result<Value> {
+    let gateway = status
+        .gateway
+        .map(KeyedUri::try_from)
+        .transpose()?
+        .map(keyed_uri);
+    // The region is only known once region params are, and those always
+    // come with a tx power
+    let region = if status.tx_power == 0 {
+        None
+    } else {
+        Some(Region::from_i32(status.region)?.to_string())
+    };
+    Ok(json!({
+        "uptime": status.uptime,
+        "forwarder": {
+            "connected": status.forwarder_connected,
+            "mac": status.forwarder_mac,
+            "last_seen": status.forwarder_last_seen,
+        },
+        "gateway": gateway,
+        "height": status.height,
+        "block_age": status.block_age,
+        "region": region,
+        "tx_power": status.tx_power,
+        "routers": routers(status.routers)?,
+        "poc": {
+            "last_beacon": status.last_beacon,
+            "last_beacon_error": status.last_beacon_error,
+            "last_witness": status.last_witness,
+            "last_witness_error": status.last_witness_error,
+        },
+        "update": {
+            "enabled": status.updater_enabled,
+            "last_check": status.last_update_check,
+            "last_check_error": status.last_update_check_error,
+        },
+    }))
+}
@@ -1,18 +1,25 @@
 mod client;
+mod http;
+pub mod json;
+mod policy;
+pub mod proto;
 mod server;
 
 const LISTEN_ADDR: &str = "127.0.0.1";
 
 pub use client::LocalClient;
-pub use helium_proto::{
-    services::local::{
-        AddGatewayReq, AddGatewayRes, ConfigReq, ConfigRes, ConfigValue, EcdhReq, EcdhRes,
-        HeightReq, HeightRes, PubkeyReq, PubkeyRes, RegionReq, RegionRes, SignReq, SignRes,
-    },
-    GatewayStakingMode,
+pub use helium_proto::GatewayStakingMode;
+pub use proto::{
+    AccountingReq, AccountingRes, AddGatewayReq, AddGatewayRes, ConfigReq, ConfigRes, ConfigValue,
+    EcdhReq, EcdhRes, HeightReq, HeightRes, PubkeyReq, PubkeyRes, RegionReq, RegionRes, ReloadReq,
+    ReloadRes, RouterRes, RoutersReq, RoutersRes, SignReq, SignRes, StatusReq, StatusRes, TapReq,
+    TapRes, TestTransmitReq, TestTransmitRes, TrafficCountersRes,
 };
 pub use server::LocalServer;
 
+use crate::Settings;
+use std::path::PathBuf;
+
 pub fn listen_addr(port: u16) -> String {
     format!("{LISTEN_ADDR}:{port}")
 }
@@ -21,3 +28,19 @@ pub fn connect_uri(port: u16) -> String {
     let listen_addr = listen_addr(port);
     format!("http://{listen_addr}")
 }
+
+/// Where the grpc API is served, either a port on localhost or a unix socket
+#[derive(Debug, Clone)]
+pub enum ApiAddress {
+    Tcp(u16),
+    Unix(PathBuf),
+}
+
+impl From<&Settings> for ApiAddress {
+    fn from(settings: &Settings) -> Self {
+        match &settings.api_socket {
+            Some(socket) => Self::Unix(socket.path.clone()),
+            None => Self::Tcp(settings.api),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! The policy for local API requests that use the gateway key.
+
+use crate::{
+    settings::{SignedMessage, SigningSettings},
+    PublicKey,
+};
+use helium_proto::{
+    services::poc_lora::{LoraBeaconReportReqV1, LoraWitnessReportReqV1},
+    BlockchainTxnAddGatewayV1, BlockchainTxnAssertLocationV2, Message,
+};
+use slog::{info, warn};
+use tonic::{Request, Status};
+
+const AUTHORIZATION: &str = "authorization";
+const BEARER: &str = "Bearer ";
+
+#[derive(Debug)]
+pub struct SigningPolicy {
+    settings: SigningSettings,
+    public_key: Vec<u8>,
+}
+
+impl SigningPolicy {
+    pub fn new(settings: &SigningSettings, public_key: &PublicKey) -> Self {
+        Self {
+            settings: settings.clone(),
+            public_key: public_key.to_vec(),
+        }
+    }
+
+    /// Checks whether the given data may be signed for the client making the
+    /// request. Denied requests are logged.
+    pub fn check_sign<T>(&self, request: &Request<T>, data: &[u8]) -> Result<(), Status> {
+        let client = self.check_client(request, "sign")?;
+        if !self.settings.sign {
+            return Err(denied(
+                request,
+                client,
+                "sign",
+                "sign requests are disabled",
+            ));
+        }
+        if self.settings.messages.is_empty() {
+            return Ok(());
+        }
+        match self
+            .settings
+            .messages
+            .iter()
+            .find(|message| is_message(**message, data, &self.public_key))
+        {
+            Some(message) => {
+                info!(slog_scope::logger(), "signing message";
+                    "message" => format!("{message:?}"),
+                    "client" => client);
+                Ok(())
+            }
+            None => Err(denied(request, client, "sign", "message type not allowed")),
+        }
+    }
+
+    /// Checks whether an add gateway transaction may be signed for the client
+    /// making the request, following the rules for sign requests of add
+    /// gateway transactions. Denied requests are logged.
+    pub fn check_add_gateway<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let client = self.check_client(request, "add_gateway")?;
+        if !self.settings.sign {
+            return Err(denied(
+                request,
+                client,
+                "add_gateway",
+                "sign requests are disabled",
+            ));
+        }
+        if !self.settings.messages.is_empty()
+            && !self.settings.messages.contains(&SignedMessage::AddGateway)
+        {
+            return Err(denied(
+                request,
+                client,
+                "add_gateway",
+                "message type not allowed",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks whether the client making the request may use ecdh. Denied
+    /// requests are logged.
+    pub fn check_ecdh<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let client = self.check_client(request, "ecdh")?;
+        if !self.settings.ecdh {
+            return Err(denied(
+                request,
+                client,
+                "ecdh",
+                "ecdh requests are disabled",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the client with the token passed in the request,
+    /// or None when any client is allowed.
+    fn check_client<T>(&self, request: &Request<T>, method: &str) -> Result<Option<&str>, Status> {
+        if self.settings.clients.is_empty() {
+            return Ok(None);
+        }
+        let token = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix(BEARER));
+        let client = token.and_then(|token| {
+            self.settings
+                .clients
+                .iter()
+                .find(|client| token_eq(&client.token, token))
+        });
+        match client {
+            Some(client) => Ok(Some(&client.name)),
+            None => Err(denied(request, None, method, "invalid client token")),
+        }
+    }
+}
+
+fn denied<T>(request: &Request<T>, client: Option<&str>, method: &str, reason: &str) -> Status {
+    warn!(slog_scope::logger(), "denied {method} request: {reason}";
+        "client" => client,
+        "remote" => request.remote_addr().map(|addr| addr.to_string()));
+    Status::permission_denied(reason)
+}
+
+/// Compares tokens in time independent of where they differ
+fn token_eq(expected: &str, token: &str) -> bool {
+    expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Decodes the data as the given message type, requiring the data to be the
+/// exact encoding of the message so no unknown fields are signed along.
+fn decode_exact<M: Message + Default>(data: &[u8]) -> Option<M> {
+    let message = M::decode(data).ok()?;
+    (message.encode_to_vec() == data).then_some(message)
+}
+
+/// Whether the data is an unsigned message of the given type for the gateway
+/// with the given key
+fn is_message(message: SignedMessage, data: &[u8], public_key: &[u8]) -> bool {
+    match message {
+        SignedMessage::AddGateway => decode_exact::<BlockchainTxnAddGatewayV1>(data)
+            .map_or(false, |txn| {
+                txn.gateway == public_key && txn.gateway_signature.is_empty()
+            }),
+        SignedMessage::AssertLocation => decode_exact::<BlockchainTxnAssertLocationV2>(data)
+            .map_or(false, |txn| {
+                txn.gateway == public_key && txn.gateway_signature.is_empty()
+            }),
+        SignedMessage::Beacon => decode_exact::<LoraBeaconReportReqV1>(data)
+            .map_or(false, |report| {
+                report.pub_key == public_key && report.signature.is_empty()
+            }),
+        SignedMessage::Witness => decode_exact::<LoraWitnessReportReqV1>(data)
+            .map_or(false, |report| {
+                report.pub_key == public_key && report.signature.is_empty()
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::ApiClientSettings;
+    use std::str::FromStr;
+
+    #[test]
+    fn sign_policy() {
+        let public_key = PublicKey::from_str("11tk4zzbyfMPYYHYda255ACoqfYFVdrUSoCWrCYfn8BoyuYrERK")
+            .expect("public key");
+        let policy = SigningPolicy::new(
+            &SigningSettings {
+                sign: true,
+                ecdh: false,
+                messages: vec![SignedMessage::Beacon],
+                clients: vec![ApiClientSettings {
+                    name: "test".to_string(),
+                    token: "secret".to_string(),
+                }],
+            },
+            &public_key,
+        );
+        let report = LoraBeaconReportReqV1 {
+            pub_key: public_key.to_vec(),
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let data = report.encode_to_vec();
+
+        let mut request = Request::new(());
+        assert!(policy.check_sign(&request, &data).is_err());
+        request
+            .metadata_mut()
+            .insert(AUTHORIZATION, "Bearer secret".parse().expect("metadata"));
+        assert!(policy.check_sign(&request, &data).is_ok());
+        assert!(policy.check_sign(&request, &[1, 2, 3]).is_err());
+        assert!(policy.check_ecdh(&request).is_err());
+        assert!(policy.check_add_gateway(&request).is_err());
+
+        let signed = LoraBeaconReportReqV1 {
+            signature: vec![1],
+            ..report
+        };
+        assert!(policy
+            .check_sign(&request, &signed.encode_to_vec())
+            .is_err());
+    }
+}
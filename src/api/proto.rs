@@ -0,0 +1,19 @@
+//! Messages and service of the local API, generated from `proto/local.proto`
+
+// Streaming rpcs are named after the snake case rpc names
+#![allow(non_camel_case_types)]
+
+tonic::include_proto!("helium.local");
+
+pub use api_client::ApiClient as Client;
+pub use api_server::{Api, ApiServer as Server};
+
+impl From<helium_proto::BlockchainVarV1> for ConfigValue {
+    fn from(v: helium_proto::BlockchainVarV1) -> Self {
+        Self {
+            name: v.name,
+            r#type: v.r#type,
+            value: v.value,
+        }
+    }
+}
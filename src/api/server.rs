@@ -1,50 +1,193 @@
 use super::{
-    listen_addr, AddGatewayReq, AddGatewayRes, ConfigReq, ConfigRes, ConfigValue, EcdhReq, EcdhRes,
-    HeightReq, HeightRes, PubkeyReq, PubkeyRes, RegionReq, RegionRes, SignReq, SignRes,
+    http, listen_addr,
+    policy::SigningPolicy,
+    proto::{Api, Server},
+    AccountingReq, AccountingRes, AddGatewayReq, AddGatewayRes, ConfigReq, ConfigRes, ConfigValue,
+    EcdhReq, EcdhRes, GatewayStakingMode, HeightReq, HeightRes, PubkeyReq, PubkeyRes, RegionReq,
+    RegionRes, ReloadReq, ReloadRes, RouterRes, RoutersReq, RoutersRes, SignReq, SignRes,
+    StatusReq, StatusRes, TapReq, TapRes, TestTransmitReq, TestTransmitRes, TrafficCountersRes,
 };
 use crate::{
-    router::dispatcher, settings::StakingMode, Error, Keypair, PublicKey, Result, Settings,
-    TxnEnvelope, TxnFee, TxnFeeConfig, CONFIG_FEE_KEYS,
+    gateway::{self, TestTransmit, TEST_PATTERN},
+    mirror::{RecordFilter, TapSender},
+    reload,
+    router::dispatcher::{self, RouterInfo},
+    settings::{ApiSocketSettings, StakingMode},
+    status::{Attempt, StatusReceivers},
+    Error, Keypair, PublicKey, Result, Settings, TxnEnvelope, TxnFee, TxnFeeConfig,
+    CONFIG_FEE_KEYS,
 };
-use futures::TryFutureExt;
+use futures::{Stream, TryFutureExt};
 use helium_crypto::Sign;
-use helium_proto::services::local::{Api, Server};
 use helium_proto::{BlockchainTxnAddGatewayV1, Message};
-use slog::{info, o, Logger};
-use std::sync::Arc;
+use serde_json::json;
+use slog::{info, o, warn, Logger};
+use std::{
+    fs,
+    os::unix::{self, fs::PermissionsExt},
+    pin::Pin,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::UnixListener;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, UnixListenerStream},
+    StreamExt,
+};
 use tonic::{self, transport::Server as TransportServer, Request, Response, Status};
 
 pub type ApiResult<T> = std::result::Result<Response<T>, Status>;
+pub type RecordStream = Pin<Box<dyn Stream<Item = std::result::Result<TapRes, Status>> + Send>>;
+
+/// Binds the unix socket for the API, replacing a stale socket left behind
+/// by an earlier run, and sets its ownership and permissions
+fn bind_socket(settings: &ApiSocketSettings) -> Result<UnixListener> {
+    let path = &settings.path;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(settings.mode))?;
+    if settings.uid.is_some() || settings.gid.is_some() {
+        unix::fs::chown(path, settings.uid, settings.gid)?;
+    }
+    Ok(listener)
+}
+
+/// Seconds since the unix epoch, or 0 for no time
+fn unix_secs(time: Option<SystemTime>) -> u64 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// The time and error of an attempt, or 0 and an empty error for none
+fn attempt_res(attempt: Option<Attempt>) -> (u64, String) {
+    match attempt {
+        Some(attempt) => (
+            unix_secs(Some(attempt.time)),
+            attempt.error.unwrap_or_default(),
+        ),
+        None => (0, String::new()),
+    }
+}
+
+fn router_res(info: RouterInfo) -> RouterRes {
+    let last_send = info.status.last_send.map(|result| Attempt {
+        time: result.time,
+        error: result.error,
+    });
+    let (last_send, last_send_error) = attempt_res(last_send);
+    RouterRes {
+        oui: info.oui,
+        uri: Some(info.uri.into()),
+        filters: info.filters as u32,
+        subnets: info.subnets as u32,
+        queued: info.status.queued as u32,
+        running: info.running,
+        last_send,
+        last_send_error,
+    }
+}
 
 pub struct LocalServer {
     dispatcher: dispatcher::MessageSender,
+    gateway: gateway::MessageSender,
+    reload: reload::MessageSender,
+    status: StatusReceivers,
+    tap: TapSender,
+    started: Instant,
     keypair: Arc<Keypair>,
     onboarding_key: PublicKey,
+    policy: SigningPolicy,
     listen_port: u16,
+    socket: Option<ApiSocketSettings>,
+    http_port: Option<u16>,
 }
 
 impl LocalServer {
-    pub fn new(dispatcher: dispatcher::MessageSender, settings: &Settings) -> Result<Self> {
+    pub fn new(
+        dispatcher: dispatcher::MessageSender,
+        gateway: gateway::MessageSender,
+        reload: reload::MessageSender,
+        status: StatusReceivers,
+        tap: TapSender,
+        settings: &Settings,
+    ) -> Result<Self> {
         Ok(Self {
+            status,
+            tap,
+            started: Instant::now(),
             keypair: settings.keypair.clone(),
             onboarding_key: settings.onboarding_key(),
+            policy: SigningPolicy::new(&settings.signing, settings.keypair.public_key()),
             listen_port: settings.api,
+            socket: settings.api_socket.clone(),
+            http_port: settings.http_api,
             dispatcher,
+            gateway,
+            reload,
         })
     }
 
     pub async fn run(self, shutdown: triggered::Listener, logger: &Logger) -> Result {
-        let addr = listen_addr(self.listen_port).parse().unwrap();
-        let logger = logger.new(o!("module" => "api", "listen" => addr));
-        info!(logger, "starting");
-        TransportServer::builder()
-            .add_service(Server::new(self))
-            .serve_with_shutdown(addr, shutdown)
-            .map_err(Error::from)
-            .await
+        let logger = logger.new(o!("module" => "api"));
+        let socket = match &self.socket {
+            Some(settings) => Some((settings.path.clone(), bind_socket(settings)?)),
+            None => None,
+        };
+        let socket_path = socket.as_ref().map(|(path, _)| path.clone());
+        let listen_port = self.listen_port;
+        let http_port = self.http_port;
+        let api = Arc::new(self);
+        let router = TransportServer::builder().add_service(Server::from_arc(api.clone()));
+        let grpc = async {
+            match socket {
+                Some((path, listener)) => {
+                    info!(logger, "starting";
+                        "socket" => path.to_string_lossy().to_string());
+                    let result = router
+                        .serve_with_incoming_shutdown(
+                            UnixListenerStream::new(listener),
+                            shutdown.clone(),
+                        )
+                        .map_err(Error::from)
+                        .await;
+                    let _ = fs::remove_file(&path);
+                    result
+                }
+                None => {
+                    let addr = listen_addr(listen_port).parse().unwrap();
+                    info!(logger, "starting"; "listen" => addr);
+                    router
+                        .serve_with_shutdown(addr, shutdown.clone())
+                        .map_err(Error::from)
+                        .await
+                }
+            }
+        };
+        let http = async {
+            match (http_port, &socket_path) {
+                // The HTTP API would open up the API restricted to the unix
+                // socket to any local process again
+                (Some(port), Some(_)) => {
+                    warn!(logger, "not starting http with an api socket"; "port" => port);
+                    Ok(())
+                }
+                (Some(port), None) => http::serve(api, port, shutdown.clone(), &logger).await,
+                (None, _) => Ok(()),
+            }
+        };
+        tokio::try_join!(grpc, http).map(|_| ())
     }
 
-    async fn _get_config<T>(&self, keys: &[T]) -> std::result::Result<Vec<ConfigValue>, Status>
+    /// Returns the requested chain variables. The values may be stale
+    /// cached ones while no validator can be reached, as indicated in the
+    /// response.
+    async fn _get_config<T>(&self, keys: &[T]) -> std::result::Result<ConfigRes, Status>
     where
         T: ToString,
     {
@@ -54,8 +197,11 @@ impl LocalServer {
             .config(&keys)
             .map_err(|err| Status::internal(format!("{err}")))
             .await?;
-        let values = reply.into_iter().map(ConfigValue::from).collect();
-        Ok(values)
+        Ok(ConfigRes {
+            values: reply.values.into_iter().map(ConfigValue::from).collect(),
+            height: reply.height,
+            stale: reply.stale,
+        })
     }
 }
 
@@ -81,6 +227,7 @@ impl Api for LocalServer {
     }
 
     async fn sign(&self, request: Request<SignReq>) -> ApiResult<SignRes> {
+        self.policy.check_sign(&request, &request.get_ref().data)?;
         let data = request.into_inner().data;
         let signature = self
             .keypair
@@ -91,14 +238,17 @@ impl Api for LocalServer {
     }
 
     async fn add_gateway(&self, request: Request<AddGatewayReq>) -> ApiResult<AddGatewayRes> {
+        self.policy.check_add_gateway(&request)?;
         let request = request.into_inner();
         let _ = PublicKey::from_bytes(&request.owner)
             .map_err(|_err| Status::invalid_argument("Invalid owner address"))?;
         let _ = PublicKey::from_bytes(&request.payer)
             .map_err(|_err| Status::invalid_argument("Invalid payer address"))?;
 
-        let mode = StakingMode::from(request.staking_mode());
-        let config_values = self._get_config(CONFIG_FEE_KEYS).await?;
+        let mode = GatewayStakingMode::from_i32(request.staking_mode)
+            .map(StakingMode::from)
+            .ok_or_else(|| Status::invalid_argument("Invalid staking mode"))?;
+        let config_values = self._get_config(CONFIG_FEE_KEYS).await?.values;
         let fee_config = TxnFeeConfig::try_from(config_values)
             .map_err(|_err| Status::internal("Failed to parse txn fees"))?;
         let mut txn = BlockchainTxnAddGatewayV1 {
@@ -129,6 +279,7 @@ impl Api for LocalServer {
     }
 
     async fn ecdh(&self, request: Request<EcdhReq>) -> ApiResult<EcdhRes> {
+        self.policy.check_ecdh(&request)?;
         let public_key = PublicKey::from_bytes(request.into_inner().address)
             .map_err(|_err| Status::invalid_argument("Invalid public key"))?;
         let secret = self
@@ -143,8 +294,8 @@ impl Api for LocalServer {
 
     async fn config(&self, request: Request<ConfigReq>) -> ApiResult<ConfigRes> {
         let keys = request.into_inner().keys;
-        let values = self._get_config(&keys).await?;
-        Ok(Response::new(ConfigRes { values }))
+        let reply = self._get_config(&keys).await?;
+        Ok(Response::new(reply))
     }
 
     async fn height(&self, _request: Request<HeightReq>) -> ApiResult<HeightRes> {
@@ -160,4 +311,135 @@ impl Api for LocalServer {
             gateway_version: reply.gateway_version,
         }))
     }
+
+    async fn routers(&self, _request: Request<RoutersReq>) -> ApiResult<RoutersRes> {
+        let infos = self
+            .dispatcher
+            .routers()
+            .map_err(|err| Status::internal(format!("{err}")))
+            .await?;
+        let routers = infos.into_iter().map(router_res).collect();
+        Ok(Response::new(RoutersRes { routers }))
+    }
+
+    async fn accounting(&self, _request: Request<AccountingReq>) -> ApiResult<AccountingRes> {
+        let entries = self
+            .dispatcher
+            .accounting()
+            .map_err(|err| Status::internal(format!("{err}")))
+            .await?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| TrafficCountersRes {
+                oui: entry.oui,
+                uri: entry.uri.unwrap_or_default(),
+                uplinks: entry.counters.uplinks,
+                bytes: entry.counters.bytes,
+                dc: entry.counters.dc,
+                downlinks: entry.counters.downlinks,
+                failures: entry.counters.failures,
+                limited: entry.limited,
+            })
+            .collect();
+        Ok(Response::new(AccountingRes { entries }))
+    }
+
+    async fn status(&self, _request: Request<StatusReq>) -> ApiResult<StatusRes> {
+        let routers = self
+            .dispatcher
+            .routers()
+            .map_err(|err| Status::internal(format!("{err}")))
+            .await?
+            .into_iter()
+            .map(router_res)
+            .collect();
+        let gateway = self.status.gateway.borrow().clone();
+        let dispatcher = self.status.dispatcher.borrow().clone();
+        let beaconer = self.status.beaconer.borrow().clone();
+        let updater = self.status.updater.borrow().clone();
+        let (last_beacon, last_beacon_error) = attempt_res(beaconer.last_beacon);
+        let (last_witness, last_witness_error) = attempt_res(beaconer.last_witness);
+        let (last_update_check, last_update_check_error) = attempt_res(updater.last_check);
+        Ok(Response::new(StatusRes {
+            forwarder_connected: gateway.connected,
+            forwarder_mac: gateway.mac.unwrap_or_default(),
+            forwarder_last_seen: unix_secs(gateway.last_seen),
+            region: gateway.region.map(i32::from).unwrap_or_default(),
+            tx_power: gateway.tx_power.unwrap_or_default(),
+            gateway: dispatcher.validator.map(Into::into),
+            height: dispatcher.height,
+            block_age: dispatcher.block_age,
+            routers,
+            last_beacon,
+            last_beacon_error,
+            last_witness,
+            last_witness_error,
+            updater_enabled: updater.enabled,
+            last_update_check,
+            last_update_check_error,
+            uptime: self.started.elapsed().as_secs(),
+        }))
+    }
+
+    type tapStream = RecordStream;
+
+    async fn tap(&self, request: Request<TapReq>) -> ApiResult<Self::tapStream> {
+        let request = request.into_inner();
+        let filter = RecordFilter::new(&request.devaddr, &request.dev_eui, &request.types)
+            .map_err(|err| Status::invalid_argument(format!("{err}")))?;
+        let stream = BroadcastStream::new(self.tap.subscribe()).filter_map(move |record| {
+            let record = match record {
+                Ok(record) if filter.matches(&record) => serde_json::to_string(&record),
+                Ok(_) => return None,
+                // Tell the client about records dropped since it could not
+                // keep up
+                Err(BroadcastStreamRecvError::Lagged(dropped)) => {
+                    serde_json::to_string(&json!({ "type": "lagged", "dropped": dropped }))
+                }
+            };
+            Some(
+                record
+                    .map(|record| TapRes { record })
+                    .map_err(|err| Status::internal(format!("{err}"))),
+            )
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn test_transmit(&self, request: Request<TestTransmitReq>) -> ApiResult<TestTransmitRes> {
+        let request = request.into_inner();
+        let datarate = request
+            .datarate
+            .parse()
+            .map_err(|_err| Status::invalid_argument("Invalid datarate"))?;
+        let payload = if request.payload.is_empty() {
+            TEST_PATTERN.to_vec()
+        } else {
+            request.payload
+        };
+        let power = self
+            .gateway
+            .test_transmit(TestTransmit {
+                frequency: request.frequency,
+                datarate,
+                // A power of 0 selects the maximum for the channel
+                power: (request.power > 0).then_some(request.power),
+                payload,
+            })
+            .map_err(|err| Status::failed_precondition(format!("{err:?}")))
+            .await?;
+        Ok(Response::new(TestTransmitRes { power }))
+    }
+
+    async fn reload(&self, _request: Request<ReloadReq>) -> ApiResult<ReloadRes> {
+        let report = self
+            .reload
+            .reload()
+            .map_err(|err| Status::failed_precondition(format!("{err}")))
+            .await?;
+        Ok(Response::new(ReloadRes {
+            applied: report.applied,
+            restart_required: report.restart_required,
+        }))
+    }
 }
@@ -5,16 +5,17 @@
 //! TODO: fuzz beacon interval to prevent thundering herd.
 
 use crate::{
-    gateway,
-    service::{entropy::EntropyService, poc::PocLoraService},
-    settings::Settings,
+    gateway, mirror,
+    service::{entropy::EntropyService, poc::PocLoraService, ConnectSettings},
+    settings::{PocSettings, Settings},
+    status::{Attempt, BeaconerStatus},
     sync, Base64, Error, Keypair, Packet, RegionParams, Result,
 };
 use futures::TryFutureExt;
 use helium_proto::services::poc_lora;
 use slog::{self, info, warn, Logger};
 use std::{sync::Arc, time::Duration};
-use tokio::time;
+use tokio::{sync::watch, time};
 use triggered::Listener;
 
 /// Message types that can be sent to `Beaconer`'s inbox.
@@ -22,6 +23,10 @@ use triggered::Listener;
 pub enum Message {
     ReceivedBeacon(Packet),
     RegionParamsChanged(RegionParams),
+    SettingsChanged {
+        poc: PocSettings,
+        connect: ConnectSettings,
+    },
 }
 
 pub type MessageSender = sync::MessageSender<Message>;
@@ -46,6 +51,17 @@ impl MessageSender {
             .send(Message::RegionParamsChanged(region_params))
             .await;
     }
+
+    /// Applies the PoC settings of reloaded settings
+    pub async fn settings_changed(&self, settings: &Settings) {
+        let _ = self
+            .0
+            .send(Message::SettingsChanged {
+                poc: settings.poc.clone(),
+                connect: ConnectSettings::from(settings),
+            })
+            .await;
+    }
 }
 
 #[derive(Debug)]
@@ -54,6 +70,8 @@ pub struct Beaconer {
     keypair: Arc<Keypair>,
     /// gateway packet transmit message queue
     transmit: gateway::MessageSender,
+    /// mirror for transmitted beacons and received witnesses
+    mirror: mirror::MessageSender,
     /// Our receive queue.
     messages: MessageReceiver,
     /// Beacon interval
@@ -62,28 +80,42 @@ pub struct Beaconer {
     region_params: Option<RegionParams>,
     poc_service: PocLoraService,
     entropy_service: EntropyService,
+    /// Results of the last beacon and witness reports
+    status: watch::Sender<BeaconerStatus>,
 }
 
 impl Beaconer {
     pub fn new(
         settings: &Settings,
         transmit: gateway::MessageSender,
+        mirror: mirror::MessageSender,
         messages: MessageReceiver,
-    ) -> Self {
+    ) -> Result<Self> {
         let interval = Duration::from_secs(settings.poc.beacon_interval);
-        let poc_service = PocLoraService::new(settings.poc.ingest_uri.clone());
-        let entropy_service = EntropyService::new(settings.poc.entropy_uri.clone());
+        let poc_service = PocLoraService::new(
+            settings.poc.ingest_uri.clone(),
+            &ConnectSettings::from(settings),
+        )?;
+        let entropy_service =
+            EntropyService::new(settings.poc.entropy_uri.clone(), settings.proxy.clone());
         let keypair = settings.keypair.clone();
 
-        Self {
+        Ok(Self {
             keypair,
             transmit,
+            mirror,
             messages,
             interval,
             region_params: None,
             poc_service,
             entropy_service,
-        }
+            status: watch::channel(BeaconerStatus::default()).0,
+        })
+    }
+
+    /// Returns a receiver for the results of the last PoC reports
+    pub fn status(&self) -> watch::Receiver<BeaconerStatus> {
+        self.status.subscribe()
     }
 
     pub async fn mk_beacon(&mut self) -> Result<beacon::Beacon> {
@@ -107,6 +139,9 @@ impl Beaconer {
             Ok(beacon) => beacon,
             Err(err) => {
                 warn!(logger, "failed to construct beacon: {err:?}");
+                let result: Result = Err(err);
+                self.status
+                    .send_modify(|status| status.last_beacon = Some(Attempt::from(&result)));
                 return;
             }
         };
@@ -119,14 +154,17 @@ impl Beaconer {
                 return;
             }
         };
+        self.mirror.beacon(&beacon);
         self.transmit.transmit_beacon(beacon).await;
 
-        let _ =  self
+        let result = self
             .poc_service
             .submit_beacon(report, self.keypair.clone())
             .inspect_err(|err| info!(logger, "failed to submit poc beacon report: {err:?}"; "beacon" => &beacon_id))
             .inspect_ok(|_| info!(logger, "poc beacon report submitted"; "beacon" => &beacon_id))
             .await;
+        self.status
+            .send_modify(|status| status.last_beacon = Some(Attempt::from(&result)));
     }
 
     async fn handle_message(&mut self, message: Message, logger: &Logger) {
@@ -135,11 +173,32 @@ impl Beaconer {
             Message::RegionParamsChanged(region_params) => {
                 self.handle_region_params(region_params, logger)
             }
+            Message::SettingsChanged { poc, connect } => {
+                self.handle_settings(poc, &connect, logger)
+            }
         }
     }
 
+    fn handle_settings(&mut self, poc: PocSettings, connect: &ConnectSettings, logger: &Logger) {
+        let poc_service = match PocLoraService::new(poc.ingest_uri.clone(), connect) {
+            Ok(poc_service) => poc_service,
+            Err(err) => {
+                warn!(logger, "ignoring invalid poc settings: {err:?}");
+                return;
+            }
+        };
+        self.poc_service = poc_service;
+        self.entropy_service = EntropyService::new(poc.entropy_uri.clone(), connect.proxy.clone());
+        self.interval = Duration::from_secs(poc.beacon_interval);
+        info!(logger, "updated poc settings";
+            "ingest_uri" => poc.ingest_uri.to_string(),
+            "entropy_uri" => poc.entropy_uri.to_string(),
+            "beacon_interval" => poc.beacon_interval);
+    }
+
     async fn handle_received_beacon(&mut self, packet: Packet, logger: &Logger) {
         info!(logger, "received possible PoC payload: {packet:?}");
+        self.mirror.witness(&packet);
         let report = match packet.to_witness_report() {
             Ok(report) => report,
             Err(err) => {
@@ -147,12 +206,14 @@ impl Beaconer {
                 return;
             }
         };
-        let _ = self
+        let result = self
             .poc_service
             .submit_witness(report.clone(), self.keypair.clone())
             .inspect_err(|err| info!(logger, "failed to submit poc witness report: {err:?}"; "beacon" => report.data.to_b64()))
             .inspect_ok(|_| info!(logger, "poc witness report submitted"; "beacon" => report.data.to_b64()))
             .await;
+        self.status
+            .send_modify(|status| status.last_witness = Some(Attempt::from(&result)));
     }
 
     fn handle_region_params(&mut self, params: RegionParams, logger: &Logger) {
@@ -188,8 +249,11 @@ impl Beaconer {
                         warn!(logger, "ignoring closed messgae channel");
                     }
                 }
-
-
+            }
+            // Restart the timer when a reload changed the beacon interval
+            if beacon_timer.period() != self.interval {
+                beacon_timer =
+                    time::interval_at(time::Instant::now() + self.interval, self.interval);
             }
         }
     }
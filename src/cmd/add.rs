@@ -1,6 +1,8 @@
 use crate::{
-    api::LocalClient, cmd::*, settings::StakingMode, Base64, PublicKey, Result, Settings,
-    TxnEnvelope,
+    api::{ApiAddress, LocalClient},
+    cmd::*,
+    settings::StakingMode,
+    Base64, PublicKey, Result, Settings, TxnEnvelope,
 };
 use helium_proto::BlockchainTxnAddGatewayV1;
 use serde_json::json;
@@ -24,7 +26,7 @@ pub struct Cmd {
 
 impl Cmd {
     pub async fn run(&self, settings: Settings) -> Result {
-        let mut client = LocalClient::new(settings.api).await?;
+        let mut client = LocalClient::new(&ApiAddress::from(&settings)).await?;
 
         let txn = client
             .add_gateway(&self.owner, &self.payer, &self.mode)
@@ -1,5 +1,5 @@
 use crate::{
-    api::{HeightRes, LocalClient},
+    api::{json, ApiAddress, HeightRes, LocalClient, RouterRes, StatusRes, TrafficCountersRes},
     cmd::*,
     keyed_uri::KeyedUri,
     service::gateway::GatewayVersion,
@@ -22,6 +22,9 @@ pub enum InfoKey {
     Name,
     Gateway,
     Region,
+    Routers,
+    Accounting,
+    Status,
 }
 
 #[derive(Debug, Clone)]
@@ -31,7 +34,9 @@ pub struct InfoKeys(pub(crate) Vec<InfoKey>);
 /// service.
 #[derive(Debug, StructOpt)]
 pub struct Cmd {
-    /// Information keys to fetch
+    /// Information keys to fetch. Use "routers" to list the router clients
+    /// and "accounting" to list the traffic counters of the running service.
+    /// Use "status" for a snapshot of the health of all service subsystems.
     #[structopt(
         long,
         short,
@@ -43,7 +48,10 @@ pub struct Cmd {
 
 impl Cmd {
     pub async fn run(&self, settings: Settings) -> Result {
-        let mut info_cache = InfoCache::new(settings.update.platform.clone(), settings.api);
+        let mut info_cache = InfoCache::new(
+            settings.update.platform.clone(),
+            ApiAddress::from(&settings),
+        );
         let mut info: HashMap<String, serde_json::Value> = HashMap::new();
         for key in &self.keys.0 {
             info.insert(key.to_string(), key.to_status(&mut info_cache).await?);
@@ -58,6 +66,9 @@ const INFO_ONBOARDING_KEY: &str = "onboarding";
 const INFO_NAME: &str = "name";
 const INFO_GATEWAY: &str = "gateway";
 const INFO_REGION: &str = "region";
+const INFO_ROUTERS: &str = "routers";
+const INFO_ACCOUNTING: &str = "accounting";
+const INFO_STATUS: &str = "status";
 
 impl fmt::Display for InfoKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -68,6 +79,9 @@ impl fmt::Display for InfoKey {
             Self::Name => INFO_NAME,
             Self::Gateway => INFO_GATEWAY,
             Self::Region => INFO_REGION,
+            Self::Routers => INFO_ROUTERS,
+            Self::Accounting => INFO_ACCOUNTING,
+            Self::Status => INFO_STATUS,
         };
         f.write_str(s)
     }
@@ -92,6 +106,9 @@ impl FromStr for InfoKey {
             INFO_NAME => Ok(Self::Name),
             INFO_GATEWAY => Ok(Self::Gateway),
             INFO_REGION => Ok(Self::Region),
+            INFO_ROUTERS => Ok(Self::Routers),
+            INFO_ACCOUNTING => Ok(Self::Accounting),
+            INFO_STATUS => Ok(Self::Status),
             invalid => Err(InfoKeyParseError(invalid.to_string())),
         }
     }
@@ -112,17 +129,17 @@ impl std::str::FromStr for InfoKeys {
 
 struct InfoCache {
     platform: String,
-    port: u16,
+    address: ApiAddress,
     public_keys: Option<(PublicKey, PublicKey)>,
     height: Option<HeightRes>,
     region: Option<Region>,
 }
 
 impl InfoCache {
-    fn new(platform: String, port: u16) -> Self {
+    fn new(platform: String, address: ApiAddress) -> Self {
         Self {
             platform,
-            port,
+            address,
             public_keys: None,
             height: None,
             region: None,
@@ -133,7 +150,7 @@ impl InfoCache {
         if let Some(public_keys) = &self.public_keys {
             return Ok(public_keys.clone());
         }
-        let mut client = LocalClient::new(self.port).await?;
+        let mut client = LocalClient::new(&self.address).await?;
         let public_keys = client.pubkey().await?;
         self.public_keys = Some(public_keys.clone());
         Ok(public_keys)
@@ -153,7 +170,7 @@ impl InfoCache {
         if let Some(height) = &self.height {
             return Ok(height.clone());
         }
-        let mut client = LocalClient::new(self.port).await?;
+        let mut client = LocalClient::new(&self.address).await?;
         let height = client.height().await?;
         self.height = Some(height.clone());
         Ok(height)
@@ -186,11 +203,26 @@ impl InfoCache {
         if let Some(region) = self.region {
             return Ok(region);
         }
-        let mut client = LocalClient::new(self.port).await?;
+        let mut client = LocalClient::new(&self.address).await?;
         let region = client.region().await?;
         self.region = Some(region);
         Ok(region)
     }
+
+    async fn routers(&mut self) -> Result<Vec<RouterRes>> {
+        let mut client = LocalClient::new(&self.address).await?;
+        client.routers().await
+    }
+
+    async fn accounting(&mut self) -> Result<Vec<TrafficCountersRes>> {
+        let mut client = LocalClient::new(&self.address).await?;
+        client.accounting().await
+    }
+
+    async fn status(&mut self) -> Result<StatusRes> {
+        let mut client = LocalClient::new(&self.address).await?;
+        client.status().await
+    }
 }
 
 impl InfoKey {
@@ -225,6 +257,29 @@ impl InfoKey {
             Self::Region => {
                 json!(cache.region().await?.to_string())
             }
+            Self::Routers => json::routers(cache.routers().await?)?,
+            Self::Accounting => {
+                let entries: Vec<serde_json::Value> = cache
+                    .accounting()
+                    .await?
+                    .into_iter()
+                    .map(|entry| {
+                        json!({
+                            "oui": entry.oui,
+                            // Entries without a uri are the totals for an OUI
+                            "uri": if entry.uri.is_empty() { None } else { Some(entry.uri) },
+                            "uplinks": entry.uplinks,
+                            "bytes": entry.bytes,
+                            "dc": entry.dc,
+                            "downlinks": entry.downlinks,
+                            "failures": entry.failures,
+                            "limited": entry.limited,
+                        })
+                    })
+                    .collect();
+                json!(entries)
+            }
+            Self::Status => json::status(cache.status().await?)?,
         };
         Ok(v)
     }
@@ -1,7 +1,10 @@
 pub mod add;
 pub mod info;
 pub mod key;
+pub mod monitor;
+pub mod reload;
 pub mod server;
+pub mod transmit;
 pub mod update;
 
 use crate::Result;
@@ -0,0 +1,47 @@
+use crate::{
+    api::{ApiAddress, LocalClient, TapReq},
+    Result, Settings,
+};
+use futures::TryStreamExt;
+use structopt::StructOpt;
+
+/// Stream uplinks, downlinks, beacons, witnesses and status events of the
+/// running service as JSON lines. Uplinks include the OUIs and default routers
+/// they were routed to and downlinks the outcome of their transmission.
+#[derive(Debug, StructOpt)]
+pub struct Cmd {
+    /// Only show packets for the given hex devaddr
+    #[structopt(long)]
+    devaddr: Option<String>,
+
+    /// Only show join requests for the given hex DevEUI
+    #[structopt(long)]
+    dev_eui: Option<String>,
+
+    /// Only show the given packet types (uplink, downlink, beacon, witness or
+    /// status). May be repeated.
+    #[structopt(long = "type")]
+    types: Vec<String>,
+}
+
+impl Cmd {
+    pub async fn run(&self, shutdown: &triggered::Listener, settings: Settings) -> Result {
+        let mut client = LocalClient::new(&ApiAddress::from(&settings)).await?;
+        let mut records = client
+            .tap(TapReq {
+                devaddr: self.devaddr.clone().unwrap_or_default(),
+                dev_eui: self.dev_eui.clone().unwrap_or_default(),
+                types: self.types.clone(),
+            })
+            .await?;
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => return Ok(()),
+                record = records.try_next() => match record? {
+                    Some(record) => println!("{}", record.record),
+                    None => return Ok(()),
+                }
+            }
+        }
+    }
+}
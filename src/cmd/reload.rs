@@ -0,0 +1,24 @@
+use crate::{
+    api::{ApiAddress, LocalClient},
+    cmd::*,
+    Result, Settings,
+};
+use serde_json::json;
+use structopt::StructOpt;
+
+/// Reload the settings of the running service, like sending it a SIGHUP.
+/// Lists the changed settings that were applied and the ones that need a
+/// restart to take effect.
+#[derive(Debug, StructOpt)]
+pub struct Cmd {}
+
+impl Cmd {
+    pub async fn run(&self, settings: Settings) -> Result {
+        let mut client = LocalClient::new(&ApiAddress::from(&settings)).await?;
+        let response = client.reload().await?;
+        print_json(&json!({
+            "applied": response.applied,
+            "restart_required": response.restart_required,
+        }))
+    }
+}
@@ -1,5 +1,6 @@
 use crate::*;
 use slog::Logger;
+use std::path::Path;
 use structopt::StructOpt;
 
 /// Run the gateway service
@@ -10,9 +11,10 @@ impl Cmd {
     pub async fn run(
         &self,
         shutdown: &triggered::Listener,
+        config: &Path,
         settings: Settings,
         logger: &Logger,
     ) -> Result {
-        server::run(shutdown, &settings, logger).await
+        server::run(shutdown, config, &settings, logger).await
     }
 }
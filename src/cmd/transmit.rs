@@ -0,0 +1,51 @@
+use crate::{
+    api::{ApiAddress, LocalClient, TestTransmitReq},
+    cmd::*,
+    Result, Settings,
+};
+use serde_json::json;
+use structopt::StructOpt;
+
+/// Transmit a test packet immediately to check that the radio transmits. The
+/// payload is sent as a proprietary LoRaWAN frame.
+#[derive(Debug, StructOpt)]
+pub struct Cmd {
+    /// Frequency in Hz, which must be a channel of the current region
+    #[structopt(long)]
+    frequency: u64,
+
+    /// Datarate to transmit at, which must be valid for the channel
+    #[structopt(long, default_value = "SF12BW125")]
+    datarate: String,
+
+    /// Tx power in dBm. Defaults to the maximum allowed for the channel.
+    #[structopt(long)]
+    power: Option<u32>,
+
+    /// Base64 encoded payload. Defaults to a test pattern.
+    #[structopt(long)]
+    payload: Option<String>,
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: Settings) -> Result {
+        let payload = match &self.payload {
+            Some(payload) => base64::decode(payload)?,
+            None => vec![],
+        };
+        let mut client = LocalClient::new(&ApiAddress::from(&settings)).await?;
+        let power = client
+            .test_transmit(TestTransmitReq {
+                frequency: self.frequency,
+                datarate: self.datarate.clone(),
+                power: self.power.unwrap_or_default(),
+                payload,
+            })
+            .await?;
+        print_json(&json!({
+            "frequency": self.frequency,
+            "datarate": self.datarate,
+            "power": power,
+        }))
+    }
+}
@@ -48,11 +48,11 @@ impl List {
     pub async fn run(&self, settings: Settings) -> Result {
         let channel = self.channel.clone().unwrap_or(settings.update.channel);
         let platform = self.platform.clone().unwrap_or(settings.update.platform);
-        let mut releases =
-            releases::filtered(releases::all(settings.update.uri.to_string()), move |r| {
-                r.in_channel(&channel) && r.asset_for_platform(&platform).is_some()
-            })
-            .take(self.count.unwrap_or(10));
+        let mut releases = releases::filtered(
+            releases::all(settings.update.uri.to_string(), settings.proxy.clone()),
+            move |r| r.in_channel(&channel) && r.asset_for_platform(&platform).is_some(),
+        )
+        .take(self.count.unwrap_or(10));
         while let Some(Ok(release)) = releases.next().await {
             if settings::version() == release.version {
                 println!("{} (*)", release.version);
@@ -69,12 +69,14 @@ impl Download {
         let platform = settings.update.platform.clone();
         let version = self.version.clone();
         let channel = Channel::from_version(&version);
-        let mut releases =
-            releases::filtered(releases::all(settings.update.uri.to_string()), move |r| {
+        let mut releases = releases::filtered(
+            releases::all(settings.update.uri.to_string(), settings.proxy.clone()),
+            move |r| {
                 r.version == version
                     && r.in_channel(&channel)
                     && r.asset_for_platform(&platform).is_some()
-            });
+            },
+        );
         let platform = settings.update.platform.clone();
         match releases.try_next().await {
             Ok(Some(release)) => {
@@ -86,7 +88,10 @@ impl Download {
                     .as_ref()
                     .unwrap_or(&env::current_dir()?)
                     .join(&asset.name);
-                match asset.download(&download_path).await {
+                match asset
+                    .download(&download_path, settings.proxy.as_ref())
+                    .await
+                {
                     Ok(()) => println!("Downloaded to: {}", download_path.to_string_lossy()),
                     Err(err) => eprintln!("Failed to download update: {:?}", err),
                 }
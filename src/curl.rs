@@ -1,20 +1,31 @@
-use crate::*;
+use crate::{proxy, settings::ProxySettings, *};
 use futures::FutureExt;
-use std::ffi::OsStr;
-use tokio::process;
+use std::{ffi::OsStr, process::Stdio};
+use tokio::{io::AsyncWriteExt, process};
 
-pub fn get<U, I, S, R, F>(url: U, args: I, f: F) -> Future<R>
+fn command<U, I, S>(url: U, args: I, proxy: Option<&ProxySettings>) -> process::Command
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+    U: AsRef<OsStr>,
+{
+    let mut command = process::Command::new("curl");
+    if let Some(proxy) = proxy {
+        command.arg("--proxy").arg(proxy::curl_uri(proxy));
+    }
+    command.kill_on_drop(true).args(args).arg("-f").arg(&url);
+    command
+}
+
+/// Fetches the given url, through the given proxy if any.
+pub fn get<U, I, S, R, F>(url: U, args: I, proxy: Option<&ProxySettings>, f: F) -> Future<R>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
     U: AsRef<OsStr>,
     F: FnOnce(&[u8]) -> Result<R> + std::marker::Send + 'static,
 {
-    process::Command::new("curl")
-        .kill_on_drop(true)
-        .args(args)
-        .arg("-f")
-        .arg(&url)
+    command(url, args, proxy)
         .output()
         .map(move |result| match result {
             Ok(output) => f(&output.stdout),
@@ -22,3 +33,44 @@ where
         })
         .boxed()
 }
+
+/// Posts the given body as JSON to the given url, through the given proxy if
+/// any. The body is passed to curl on stdin to keep it off the command line.
+pub fn post<U, F, R>(url: U, body: String, proxy: Option<&ProxySettings>, f: F) -> Future<R>
+where
+    U: AsRef<OsStr>,
+    F: FnOnce(&[u8]) -> Result<R> + std::marker::Send + 'static,
+{
+    let mut command = command(
+        url,
+        [
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ],
+        proxy,
+    );
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    async move {
+        let mut child = command.spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(body.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(Error::custom(format!(
+                "curl post failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        f(&output.stdout)
+    }
+    .boxed()
+}
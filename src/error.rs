@@ -25,6 +25,8 @@ pub enum Error {
     Beacon(#[from] beacon::Error),
     #[error("region error")]
     Region(#[from] RegionError),
+    #[error("mqtt error")]
+    Mqtt(#[from] rumqttc::ClientError),
 }
 
 #[derive(Error, Debug)]
@@ -43,6 +45,8 @@ pub enum DecodeError {
     Json(#[from] serde_json::Error),
     #[error("base64 decode")]
     Base64(#[from] base64::DecodeError),
+    #[error("hex decode")]
+    Hex(#[from] hex::FromHexError),
     #[error("network address decode")]
     Addr(#[from] net::AddrParseError),
     #[error("protobuf decode")]
@@ -65,6 +69,8 @@ pub enum ServiceError {
     Service(#[from] helium_proto::services::Error),
     #[error("rpc {0:?}")]
     Rpc(#[from] tonic::Status),
+    #[error("http {0:?}")]
+    Http(#[from] hyper::Error),
     #[error("stream closed")]
     Stream,
     #[error("channel closed")]
@@ -96,6 +102,7 @@ macro_rules! from_err {
 // Service Errors
 from_err!(ServiceError, helium_proto::services::Error);
 from_err!(ServiceError, tonic::Status);
+from_err!(ServiceError, hyper::Error);
 
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
     fn from(_err: tokio::sync::mpsc::error::SendError<T>) -> Self {
@@ -109,6 +116,7 @@ from_err!(EncodeError, prost::EncodeError);
 // Decode Errors
 from_err!(DecodeError, http::uri::InvalidUri);
 from_err!(DecodeError, base64::DecodeError);
+from_err!(DecodeError, hex::FromHexError);
 from_err!(DecodeError, serde_json::Error);
 from_err!(DecodeError, net::AddrParseError);
 from_err!(DecodeError, prost::DecodeError);
@@ -162,4 +170,19 @@ impl Error {
     pub fn gateway_service_check(block_age: u64, max_age: u64) -> Error {
         Error::Service(ServiceError::Check { block_age, max_age })
     }
+
+    /// Whether this is an error reaching a service rather than the service
+    /// rejecting a request
+    pub fn is_transport(&self) -> bool {
+        match self {
+            Error::Service(ServiceError::Service(_)) | Error::Service(ServiceError::Http(_)) => {
+                true
+            }
+            Error::Service(ServiceError::Rpc(status)) => matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded | tonic::Code::Cancelled
+            ),
+            _ => false,
+        }
+    }
 }
@@ -1,27 +1,56 @@
-use crate::{beaconer, router::dispatcher, Error, Packet, RegionParams, Result, Settings};
+use crate::{
+    beaconer,
+    mirror::{self, DownlinkOutcome, StatusEvent},
+    router::dispatcher,
+    status::GatewayStatus,
+    sync, Error, Packet, RegionParams, Result, Settings,
+};
 use beacon::Beacon;
 use futures::TryFutureExt;
 use lorawan::PHYPayload;
 use semtech_udp::{
     pull_resp,
     server_runtime::{Error as SemtechError, Event, UdpRuntime},
-    tx_ack, CodingRate, MacAddress, Modulation,
+    tx_ack, CodingRate, DataRate, MacAddress, Modulation,
 };
 use slog::{debug, info, o, warn, Logger};
 use std::{
     convert::TryFrom,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
 pub const DOWNLINK_TIMEOUT_SECS: u64 = 5;
 pub const UPLINK_TIMEOUT_SECS: u64 = 6;
+/// The payload of a test transmission when none is given
+pub const TEST_PATTERN: &[u8] = b"helium gateway test transmit";
 
 #[derive(Debug)]
 pub enum Message {
     Downlink(Packet),
+    /// A downlink injected through a mirror, which is checked against the
+    /// region params before it is transmitted
+    Inject(Packet),
     TransmitBeacon(Beacon),
     RegionParamsChanged(RegionParams),
+    TestTransmit {
+        transmit: TestTransmit,
+        response: sync::ResponseSender<Result<u32>>,
+    },
+}
+
+/// An immediate transmission to check that the radio transmits
+#[derive(Debug, Clone)]
+pub struct TestTransmit {
+    /// Frequency in Hz, which must be a channel of the region
+    pub frequency: u64,
+    /// Datarate, which must be one of the datarates of the channel
+    pub datarate: DataRate,
+    /// Tx power in dBm. Defaults to the maximum for the channel.
+    pub power: Option<u32>,
+    /// The data to transmit, sent as a proprietary frame so LoRaWAN receivers
+    /// ignore it
+    pub payload: Vec<u8>,
 }
 
 #[derive(Clone, Debug)]
@@ -41,6 +70,13 @@ impl MessageSender {
             .await
     }
 
+    pub async fn inject(&self, packet: Packet) -> Result {
+        self.0
+            .send(Message::Inject(packet))
+            .map_err(|_| Error::channel())
+            .await
+    }
+
     /// Send a non-inverted (`ipol = false`) beacon packet that is receivable by
     /// other gateways.
     ///
@@ -54,6 +90,20 @@ impl MessageSender {
             .await;
     }
 
+    /// Transmits the given test transmission and returns the tx power used
+    /// once the packet forwarder acknowledged the transmission.
+    pub async fn test_transmit(&self, transmit: TestTransmit) -> Result<u32> {
+        let (tx, rx) = sync::response_channel();
+        let _ = self
+            .0
+            .send(Message::TestTransmit {
+                transmit,
+                response: tx,
+            })
+            .await;
+        rx.recv().await?
+    }
+
     pub async fn region_params_changed(&self, region_params: RegionParams) {
         let _ = self
             .0
@@ -66,10 +116,12 @@ pub struct Gateway {
     uplinks: dispatcher::MessageSender,
     messages: MessageReceiver,
     beacon_handler: beaconer::MessageSender,
+    mirror: mirror::MessageSender,
     downlink_mac: MacAddress,
     udp_runtime: UdpRuntime,
     listen_address: String,
     region_params: Option<RegionParams>,
+    status: watch::Sender<GatewayStatus>,
 }
 
 impl Gateway {
@@ -77,6 +129,7 @@ impl Gateway {
         uplinks: dispatcher::MessageSender,
         messages: MessageReceiver,
         beacon_handler: beaconer::MessageSender,
+        mirror: mirror::MessageSender,
         settings: &Settings,
     ) -> Result<Self> {
         let gateway = Gateway {
@@ -84,13 +137,25 @@ impl Gateway {
             downlink_mac: Default::default(),
             messages,
             beacon_handler,
+            mirror,
             listen_address: settings.listen.clone(),
             udp_runtime: UdpRuntime::new(&settings.listen).await?,
             region_params: None,
+            status: watch::channel(GatewayStatus::default()).0,
         };
         Ok(gateway)
     }
 
+    /// Returns a receiver for the status of the packet forwarder connection
+    pub fn status(&self) -> watch::Receiver<GatewayStatus> {
+        self.status.subscribe()
+    }
+
+    fn seen_forwarder(&self) {
+        self.status
+            .send_modify(|status| status.last_seen = Some(SystemTime::now()));
+    }
+
     pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
         let logger = logger.new(o!("module" => "gateway"));
         info!(logger, "starting"; "listen" => &self.listen_address);
@@ -123,27 +188,43 @@ impl Gateway {
             }
             Event::NewClient((mac, addr)) => {
                 info!(logger, "new packet forwarder client: {mac}, {addr}");
+                self.mirror.status(StatusEvent::ForwarderConnected {
+                    mac: mac.to_string(),
+                });
                 self.downlink_mac = mac;
+                self.status.send_modify(|status| {
+                    status.connected = true;
+                    status.mac = Some(mac.to_string());
+                    status.last_seen = Some(SystemTime::now());
+                });
             }
             Event::UpdateClient((mac, addr)) => {
                 info!(logger, "mac existed, but IP updated: {mac}, {addr}")
             }
             Event::ClientDisconnected((mac, addr)) => {
-                info!(logger, "disconnected packet forwarder: {mac}, {addr}")
+                info!(logger, "disconnected packet forwarder: {mac}, {addr}");
+                self.mirror.status(StatusEvent::ForwarderDisconnected {
+                    mac: mac.to_string(),
+                });
+                self.status.send_modify(|status| status.connected = false);
             }
-            Event::PacketReceived(rxpk, _gateway_mac) => match Packet::try_from(rxpk) {
-                Ok(packet) if packet.is_potential_beacon() => {
-                    self.beacon_handler.received_beacon(packet).await
-                }
-                Ok(packet) => self.handle_uplink(logger, packet, Instant::now()).await,
-                Err(err) => {
-                    warn!(logger, "ignoring push_data: {err:?}");
+            Event::PacketReceived(rxpk, _gateway_mac) => {
+                self.seen_forwarder();
+                match Packet::try_from(rxpk) {
+                    Ok(packet) if packet.is_potential_beacon() => {
+                        self.beacon_handler.received_beacon(packet).await
+                    }
+                    Ok(packet) => self.handle_uplink(logger, packet, Instant::now()).await,
+                    Err(err) => {
+                        warn!(logger, "ignoring push_data: {err:?}");
+                    }
                 }
-            },
+            }
             Event::NoClientWithMac(_packet, mac) => {
                 info!(logger, "ignoring send to client with unknown MAC: {mac}")
             }
             Event::StatReceived(stat, mac) => {
+                self.seen_forwarder();
                 debug!(logger, "mac: {mac}, stat: {stat:?}")
             }
         };
@@ -161,11 +242,21 @@ impl Gateway {
     async fn handle_message(&mut self, logger: &Logger, message: Message) {
         match message {
             Message::Downlink(packet) => self.handle_downlink(logger, packet).await,
+            Message::Inject(packet) => self.handle_inject(logger, packet).await,
             Message::TransmitBeacon(beacon) => self.handle_transmit_beacon(logger, beacon).await,
+            Message::TestTransmit { transmit, response } => {
+                self.handle_test_transmit(logger, transmit, response)
+            }
             Message::RegionParamsChanged(region_params) => {
                 self.beacon_handler
                     .region_params_changed(region_params.clone())
                     .await;
+                self.mirror
+                    .status(StatusEvent::region_changed(region_params.region));
+                self.status.send_modify(|status| {
+                    status.region = Some(region_params.region);
+                    status.tx_power = region_params.tx_power();
+                });
                 self.region_params = Some(region_params);
                 info!(logger, "updated region";
                     "region" => RegionParams::to_string(&self.region_params));
@@ -213,6 +304,96 @@ impl Gateway {
         });
     }
 
+    fn handle_test_transmit(
+        &mut self,
+        logger: &Logger,
+        transmit: TestTransmit,
+        response: sync::ResponseSender<Result<u32>>,
+    ) {
+        let packet = match self.test_transmit_pull_resp(&transmit) {
+            Ok(packet) => packet,
+            Err(err) => {
+                warn!(logger, "ignoring test transmit: {err:?}");
+                response.send(Err(err), logger);
+                return;
+            }
+        };
+        let tx_power = packet.powe as u32;
+        info!(logger, "test transmit {} via {}", packet, self.downlink_mac);
+        let test_tx = self.udp_runtime.prepare_downlink(packet, self.downlink_mac);
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            let result = test_tx
+                .dispatch(Some(Duration::from_secs(DOWNLINK_TIMEOUT_SECS)))
+                .await
+                .map(|_| tx_power)
+                .map_err(|err| Error::custom(format!("test transmit failed: {err:?}")));
+            response.send(result, &logger);
+        });
+    }
+
+    /// Checks a test transmission against the region params and constructs
+    /// the packet to transmit.
+    fn test_transmit_pull_resp(&self, transmit: &TestTransmit) -> Result<pull_resp::TxPk> {
+        let region_params = self
+            .region_params
+            .as_ref()
+            .ok_or_else(|| Error::custom("no region params"))?;
+        let max_power = region_params
+            .channel_tx_power(transmit.frequency)
+            .ok_or_else(|| {
+                Error::custom(format!(
+                    "frequency {} is not a channel of region {}",
+                    transmit.frequency, region_params.region
+                ))
+            })?;
+        let tx_power = match transmit.power {
+            Some(power) if power > max_power => {
+                return Err(Error::custom(format!(
+                    "power {power} dBm exceeds the {max_power} dBm limit of the channel"
+                )))
+            }
+            Some(power) => power,
+            None => max_power,
+        };
+        region_params.check_datarate(transmit.frequency, &transmit.datarate.to_string())?;
+        let data: Vec<u8> = PHYPayload::proprietary(transmit.payload.as_slice()).try_into()?;
+        Ok(pull_resp::TxPk {
+            imme: true,
+            ipol: false,
+            modu: Modulation::LORA,
+            codr: CodingRate::_4_5,
+            datr: transmit.datarate.clone(),
+            // convert hz to mhz
+            freq: transmit.frequency as f64 / 1e6,
+            size: data.len() as u64,
+            data,
+            powe: tx_power as u64,
+            rfch: 0,
+            tmst: None,
+            tmms: None,
+            fdev: None,
+            prea: None,
+            ncrc: None,
+        })
+    }
+
+    /// Transmits an injected downlink if its frequency and datarate are valid
+    /// for the region.
+    async fn handle_inject(&mut self, logger: &Logger, downlink: Packet) {
+        // The frequency is an f32 in MHz, so round it to whole kHz
+        let frequency = (downlink.frequency as f64 * 1e3).round() as u64 * 1000;
+        let checked = self
+            .region_params
+            .as_ref()
+            .ok_or_else(|| Error::custom("no region params"))
+            .and_then(|params| params.check_datarate(frequency, &downlink.datarate));
+        match checked {
+            Ok(()) => self.handle_downlink(logger, downlink).await,
+            Err(err) => warn!(logger, "ignoring injected downlink: {err}"),
+        }
+    }
+
     async fn handle_downlink(&mut self, logger: &Logger, downlink: Packet) {
         let region_params = if let Some(region_params) = &self.region_params {
             region_params
@@ -233,6 +414,7 @@ impl Gateway {
             self.udp_runtime.prepare_empty_downlink(self.downlink_mac),
         );
         let logger = logger.clone();
+        let mirror = self.mirror.clone();
         tokio::spawn(async move {
             match downlink.to_pull_resp(false, tx_power).unwrap() {
                 None => (),
@@ -244,7 +426,7 @@ impl Gateway {
                         downlink_rx1.get_destination_mac()
                     );
                     downlink_rx1.set_packet(txpk);
-                    match downlink_rx1
+                    let outcome = match downlink_rx1
                         .dispatch(Some(Duration::from_secs(DOWNLINK_TIMEOUT_SECS)))
                         .await
                     {
@@ -259,19 +441,27 @@ impl Gateway {
                                     downlink_rx2.get_destination_mac()
                                 );
                                 downlink_rx2.set_packet(txpk);
-                                if let Err(err) = downlink_rx2
+                                match downlink_rx2
                                     .dispatch(Some(Duration::from_secs(DOWNLINK_TIMEOUT_SECS)))
                                     .await
                                 {
-                                    warn!(logger, "ignoring rx2 downlink error: {:?}", err);
+                                    Ok(()) => DownlinkOutcome::Rx2,
+                                    Err(err) => {
+                                        warn!(logger, "ignoring rx2 downlink error: {:?}", err);
+                                        DownlinkOutcome::Failed(format!("{err:?}"))
+                                    }
                                 }
+                            } else {
+                                DownlinkOutcome::Failed("rx1 timing, no rx2 window".to_string())
                             }
                         }
                         Err(err) => {
                             warn!(logger, "ignoring rx1 downlink error: {:?}", err);
+                            DownlinkOutcome::Failed(format!("{err:?}"))
                         }
-                        Ok(()) => (),
-                    }
+                        Ok(()) => DownlinkOutcome::Rx1,
+                    };
+                    mirror.downlink(&downlink, outcome);
                 }
             }
         });
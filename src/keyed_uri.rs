@@ -1,4 +1,4 @@
-use crate::{PublicKey, Result};
+use crate::{api::proto, PublicKey, Result};
 use http::Uri;
 use serde::Deserialize;
 use std::{
@@ -38,9 +38,9 @@ impl fmt::Debug for KeyedUri {
     }
 }
 
-impl TryFrom<helium_proto::services::local::KeyedUri> for KeyedUri {
+impl TryFrom<proto::KeyedUri> for KeyedUri {
     type Error = crate::Error;
-    fn try_from(v: helium_proto::services::local::KeyedUri) -> Result<Self> {
+    fn try_from(v: proto::KeyedUri) -> Result<Self> {
         let result = Self {
             uri: http::Uri::from_str(&v.uri)?,
             pubkey: Arc::new(helium_crypto::PublicKey::from_bytes(v.address)?),
@@ -49,9 +49,9 @@ impl TryFrom<helium_proto::services::local::KeyedUri> for KeyedUri {
     }
 }
 
-impl From<KeyedUri> for helium_proto::services::local::KeyedUri {
+impl From<KeyedUri> for proto::KeyedUri {
     fn from(v: KeyedUri) -> Self {
-        helium_proto::services::local::KeyedUri {
+        proto::KeyedUri {
             address: v.pubkey.to_vec(),
             uri: v.uri.to_string(),
         }
@@ -5,13 +5,18 @@ pub mod error;
 pub mod gateway;
 pub mod keyed_uri;
 pub mod keypair;
+pub mod mirror;
 pub mod packet;
+pub mod proxy;
 pub mod region;
+pub mod reload;
+pub mod roaming;
 pub mod router;
 pub mod server;
 pub mod service;
 pub mod settings;
 pub mod state_channel;
+pub mod status;
 pub mod sync;
 pub mod updater;
 
@@ -1,7 +1,7 @@
 use gateway_rs::{
     cmd,
     error::Result,
-    settings::{LogMethod, Settings},
+    settings::{log_level::RuntimeLevelFilter, LogMethod, Settings},
 };
 use slog::{self, debug, error, o, Drain, Logger};
 use std::{io, path::PathBuf};
@@ -37,6 +37,9 @@ pub enum Cmd {
     Update(cmd::update::Cmd),
     Server(cmd::server::Cmd),
     Add(Box<cmd::add::Cmd>),
+    Monitor(cmd::monitor::Cmd),
+    TestTransmit(cmd::transmit::Cmd),
+    Reload(cmd::reload::Cmd),
 }
 
 /// An empty timestamp function for when timestamp should not be included in
@@ -52,9 +55,7 @@ fn mk_logger(settings: &Settings) -> Logger {
             let drain = slog_syslog::unix_3164(slog_syslog::Facility::LOG_USER)
                 .expect("syslog drain")
                 .fuse();
-            slog_async::Async::new(drain)
-                .build()
-                .filter_level(settings.log.level.into())
+            RuntimeLevelFilter::new(slog_async::Async::new(drain).build(), settings.log.level)
                 .fuse()
         }
         LogMethod::Stdio => {
@@ -68,9 +69,7 @@ fn mk_logger(settings: &Settings) -> Logger {
                 .use_custom_timestamp(timestamp)
                 .build()
                 .fuse();
-            slog_async::Async::new(drain)
-                .build()
-                .filter_level(settings.log.level.into())
+            RuntimeLevelFilter::new(slog_async::Async::new(drain).build(), settings.log.level)
                 .fuse()
         }
     };
@@ -150,6 +149,12 @@ pub async fn run(
         Cmd::Info(cmd) => cmd.run(settings).await,
         Cmd::Update(cmd) => cmd.run(settings).await,
         Cmd::Add(cmd) => cmd.run(settings).await,
-        Cmd::Server(cmd) => cmd.run(shutdown_listener, settings, &logger).await,
+        Cmd::Monitor(cmd) => cmd.run(shutdown_listener, settings).await,
+        Cmd::TestTransmit(cmd) => cmd.run(settings).await,
+        Cmd::Reload(cmd) => cmd.run(settings).await,
+        Cmd::Server(cmd) => {
+            cmd.run(shutdown_listener, &cli.config, settings, &logger)
+                .await
+        }
     }
 }
@@ -0,0 +1,320 @@
+//! This module mirrors uplinks, downlinks, proof-of-coverage beacons and
+//! witnesses and gateway status events to local sinks and to the packet taps
+//! of the local API.
+//!
+//! Mirroring is best effort. Records are dropped rather than slowing down
+//! packet delivery when the mirror can not keep up with the sinks or taps.
+
+mod mqtt;
+mod sink;
+mod tap;
+mod webhook;
+
+pub use mqtt::InjectedDownlink;
+pub use sink::Sink;
+pub use tap::RecordFilter;
+
+use crate::{gateway, sync, Base64, KeyedUri, Packet, Region, Result, Settings};
+use beacon::Beacon;
+use helium_proto::{routing_information::Data as RoutingData, RoutingInformation};
+use serde::Serialize;
+use slog::{info, o, warn, Logger};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Number of records buffered for each packet tap
+const TAP_CAPACITY: usize = 100;
+
+/// A record of a packet as mirrored to the sinks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Uplink {
+        #[serde(flatten)]
+        packet: PacketRecord,
+        /// The OUIs the uplink was routed to
+        ouis: Vec<u32>,
+        /// The uris of the default routers the uplink was sent to
+        default_routers: Vec<String>,
+    },
+    Downlink {
+        #[serde(flatten)]
+        packet: PacketRecord,
+        outcome: DownlinkOutcome,
+    },
+    /// A proof-of-coverage beacon transmitted by this gateway
+    Beacon {
+        time: u64,
+        beacon_id: String,
+        /// Frequency in Hz
+        frequency: u64,
+        datr: String,
+        data: String,
+    },
+    /// A proof-of-coverage beacon received by this gateway
+    Witness {
+        #[serde(flatten)]
+        packet: PacketRecord,
+    },
+    Status {
+        time: u64,
+        #[serde(flatten)]
+        event: StatusEvent,
+    },
+}
+
+impl Record {
+    /// Returns a short name for the kind of record
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Uplink { .. } => "uplink",
+            Self::Downlink { .. } => "downlink",
+            Self::Beacon { .. } => "beacon",
+            Self::Witness { .. } => "witness",
+            Self::Status { .. } => "status",
+        }
+    }
+}
+
+/// Changes in the state of the gateway.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StatusEvent {
+    ForwarderConnected { mac: String },
+    ForwarderDisconnected { mac: String },
+    RegionChanged { region: String },
+    ValidatorChanged { pubkey: String, uri: String },
+}
+
+impl StatusEvent {
+    pub fn region_changed(region: Region) -> Self {
+        Self::RegionChanged {
+            region: region.to_string(),
+        }
+    }
+
+    pub fn validator_changed(uri: &KeyedUri) -> Self {
+        Self::ValidatorChanged {
+            pubkey: uri.pubkey.to_string(),
+            uri: uri.uri.to_string(),
+        }
+    }
+}
+
+/// Packet metadata using the semtech field names where possible.
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketRecord {
+    /// System time in milliseconds since the unix epoch
+    pub time: u64,
+    /// Concentrator timestamp in microseconds
+    pub tmst: u64,
+    /// Frequency in MHz
+    pub freq: f32,
+    pub datr: String,
+    pub rssi: f32,
+    pub lsnr: f32,
+    pub size: usize,
+    /// Base64 encoded payload
+    pub data: String,
+    pub routing: Option<RoutingRecord>,
+}
+
+/// Decoded routing information for a packet.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingRecord {
+    Devaddr(String),
+    Eui { dev_eui: String, app_eui: String },
+}
+
+/// The result of transmitting a downlink.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownlinkOutcome {
+    Rx1,
+    Rx2,
+    Failed(String),
+    /// The downlink of the given OUI was not transmitted since the downlink of
+    /// another OUI was selected for the same uplink
+    Discarded {
+        oui: u32,
+        selected_oui: u32,
+    },
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl From<&Packet> for PacketRecord {
+    fn from(packet: &Packet) -> Self {
+        Self {
+            time: now_millis(),
+            tmst: packet.timestamp,
+            freq: packet.frequency,
+            datr: packet.datarate.clone(),
+            rssi: packet.signal_strength,
+            lsnr: packet.snr,
+            size: packet.payload().len(),
+            data: packet.payload().to_b64(),
+            routing: packet.routing().as_ref().and_then(RoutingRecord::from_info),
+        }
+    }
+}
+
+impl RoutingRecord {
+    fn from_info(info: &RoutingInformation) -> Option<Self> {
+        match &info.data {
+            Some(RoutingData::Devaddr(devaddr)) => Some(Self::Devaddr(format!("{devaddr:08X}"))),
+            Some(RoutingData::Eui(eui)) => Some(Self::Eui {
+                dev_eui: format!("{:016X}", eui.deveui),
+                app_eui: format!("{:016X}", eui.appeui),
+            }),
+            None => None,
+        }
+    }
+}
+
+pub type MessageSender = sync::MessageSender<Record>;
+pub type MessageReceiver = sync::MessageReceiver<Record>;
+
+pub fn message_channel(size: usize) -> (MessageSender, MessageReceiver) {
+    sync::message_channel(size)
+}
+
+impl MessageSender {
+    pub fn uplink(&self, packet: &Packet, ouis: Vec<u32>, default_routers: Vec<String>) {
+        if self.0.is_closed() {
+            return;
+        }
+        self.record(Record::Uplink {
+            packet: packet.into(),
+            ouis,
+            default_routers,
+        })
+    }
+
+    pub fn downlink(&self, packet: &Packet, outcome: DownlinkOutcome) {
+        if self.0.is_closed() {
+            return;
+        }
+        let mut record = PacketRecord::from(packet);
+        if record.routing.is_none() {
+            // Downlinks carry no routing information, so take the devaddr
+            // from the frame
+            record.routing = Packet::parse_frame(lorawan::Direction::Downlink, packet.payload())
+                .and_then(|frame| Packet::routing_information(&frame))
+                .ok()
+                .flatten()
+                .as_ref()
+                .and_then(RoutingRecord::from_info);
+        }
+        self.record(Record::Downlink {
+            packet: record,
+            outcome,
+        })
+    }
+
+    pub fn beacon(&self, beacon: &Beacon) {
+        if self.0.is_closed() {
+            return;
+        }
+        self.record(Record::Beacon {
+            time: now_millis(),
+            beacon_id: beacon.beacon_id(),
+            frequency: beacon.frequency,
+            datr: beacon.datarate.to_string(),
+            data: beacon.data.to_b64(),
+        })
+    }
+
+    pub fn witness(&self, packet: &Packet) {
+        if self.0.is_closed() {
+            return;
+        }
+        self.record(Record::Witness {
+            packet: packet.into(),
+        })
+    }
+
+    pub fn status(&self, event: StatusEvent) {
+        if self.0.is_closed() {
+            return;
+        }
+        self.record(Record::Status {
+            time: now_millis(),
+            event,
+        })
+    }
+
+    fn record(&self, record: Record) {
+        // Never block packet delivery on the mirror
+        let _ = self.0.try_send(record);
+    }
+}
+
+/// Sender of mirrored records to packet taps. Each tap subscribes to
+/// receive the records mirrored while it is subscribed.
+pub type TapSender = broadcast::Sender<Record>;
+
+pub struct Mirror {
+    sinks: Vec<Sink>,
+    messages: MessageReceiver,
+    tap: TapSender,
+}
+
+impl Mirror {
+    pub async fn new(
+        settings: &Settings,
+        downlinks: gateway::MessageSender,
+        messages: MessageReceiver,
+        shutdown: &triggered::Listener,
+    ) -> Result<Self> {
+        let mut sinks = Vec::with_capacity(settings.mirrors.len());
+        for sink_settings in &settings.mirrors {
+            sinks.push(Sink::new(sink_settings, settings, downlinks.clone(), shutdown).await?);
+        }
+        Ok(Self {
+            sinks,
+            messages,
+            tap: broadcast::channel(TAP_CAPACITY).0,
+        })
+    }
+
+    /// Returns the sender to subscribe packet taps to
+    pub fn tap(&self) -> TapSender {
+        self.tap.clone()
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "mirror"));
+        // The mirror keeps running without sinks to serve packet taps
+        info!(logger, "starting"; "sinks" => self.sinks.len());
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                record = self.messages.recv() => match record {
+                    Some(record) => {
+                        // Sending fails when no tap is subscribed
+                        let _ = self.tap.send(record.clone());
+                        for sink in self.sinks.iter_mut() {
+                            if let Err(err) = sink.send(&record).await {
+                                warn!(logger, "failed to mirror record: {err:?}"; "sink" => sink.to_string());
+                            }
+                        }
+                    },
+                    None => {
+                        warn!(logger, "ignoring closed mirror channel");
+                        return Ok(())
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use super::Record;
+use crate::{gateway, Error, Packet, Result};
+use helium_proto::packet::PacketType;
+use http::Uri;
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, MqttOptions, QoS};
+use serde::Deserialize;
+use slog::{info, o, warn, Logger};
+use std::{fmt, time::Duration};
+use tokio::time;
+
+const MQTT_DEFAULT_PORT: u16 = 1883;
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+const MQTT_RECONNECT_WAIT: Duration = Duration::from_secs(5);
+const MQTT_CHANNEL_SIZE: usize = 20;
+const MQTT_INJECT_TOPIC: &str = "tx";
+
+pub struct MqttSink {
+    uri: Uri,
+    topic: String,
+    client: AsyncClient,
+    /// Records dropped since the client last accepted one
+    dropped: u64,
+    logger: Logger,
+}
+
+impl fmt::Display for MqttSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.uri)
+    }
+}
+
+/// A downlink published to the inject topic of the MQTT broker.
+///
+/// Injected downlinks are not authenticated, so anyone who can publish to the
+/// inject topic can make the gateway transmit. The gateway only checks that
+/// the frequency and datarate are valid for its region, so the broker should
+/// restrict who can publish to the topic.
+#[derive(Debug, Deserialize)]
+pub struct InjectedDownlink {
+    /// Concentrator timestamp in microseconds to transmit at
+    pub tmst: u64,
+    /// Frequency in MHz
+    pub freq: f32,
+    pub datr: String,
+    /// Base64 encoded payload
+    pub data: String,
+}
+
+impl TryFrom<InjectedDownlink> for Packet {
+    type Error = Error;
+    fn try_from(v: InjectedDownlink) -> Result<Self> {
+        let packet = helium_proto::Packet {
+            r#type: PacketType::Lorawan.into(),
+            signal_strength: 0.0,
+            snr: 0.0,
+            frequency: v.freq,
+            timestamp: v.tmst,
+            datarate: v.datr,
+            routing: None,
+            payload: base64::decode(&v.data)?,
+            rx2_window: None,
+            oui: 0,
+        };
+        Ok(Self::from(packet))
+    }
+}
+
+impl MqttSink {
+    pub fn new(
+        uri: &Uri,
+        topic: &str,
+        client_id: String,
+        credentials: Option<(String, String)>,
+        downlinks: gateway::MessageSender,
+        shutdown: triggered::Listener,
+    ) -> Result<Self> {
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::custom(format!("missing mqtt host in {uri}")))?;
+        let port = uri.port_u16().unwrap_or(MQTT_DEFAULT_PORT);
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(MQTT_KEEP_ALIVE);
+        if let Some((username, password)) = credentials {
+            options.set_credentials(username, password);
+        }
+        let (client, eventloop) = AsyncClient::new(options, MQTT_CHANNEL_SIZE);
+        // We start the mqtt scope at the root logger to avoid picking up the
+        // previously set KV pairs (which causes dupes)
+        let logger = slog_scope::logger().new(o!(
+            "module" => "mqtt",
+            "uri" => uri.to_string(),
+        ));
+        let inject_topic = format!("{topic}/{MQTT_INJECT_TOPIC}");
+        tokio::spawn(run_eventloop(
+            eventloop,
+            client.clone(),
+            inject_topic,
+            downlinks,
+            shutdown,
+            logger.clone(),
+        ));
+        Ok(Self {
+            uri: uri.clone(),
+            topic: topic.to_string(),
+            client,
+            dropped: 0,
+            logger,
+        })
+    }
+
+    /// Queues the record for publishing without waiting. Records are dropped
+    /// while the client's queue is full, for example while the broker can
+    /// not be reached.
+    pub fn send(&mut self, record: &Record) -> Result {
+        let topic = format!("{}/{}", self.topic, record.kind());
+        let payload = serde_json::to_vec(record)?;
+        match self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, false, payload)
+        {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    info!(self.logger, "resumed publishing";
+                        "dropped" => self.dropped);
+                    self.dropped = 0;
+                }
+            }
+            Err(err) => {
+                if self.dropped == 0 {
+                    warn!(self.logger, "dropping records: {err:?}");
+                }
+                self.dropped += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drives the MQTT connection until shutdown. The event loop reconnects on
+/// errors and (re)subscribes to the inject topic on every connect.
+async fn run_eventloop(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    inject_topic: String,
+    downlinks: gateway::MessageSender,
+    shutdown: triggered::Listener,
+    logger: Logger,
+) {
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.clone() => {
+                info!(logger, "shutting down");
+                return;
+            },
+            event = eventloop.poll() => event,
+        };
+        match event {
+            Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                info!(logger, "connected");
+                if let Err(err) = client.try_subscribe(&inject_topic, QoS::AtMostOnce) {
+                    warn!(logger, "failed to subscribe to {inject_topic}: {err:?}");
+                }
+            }
+            Ok(Event::Incoming(Incoming::Publish(publish))) if publish.topic == inject_topic => {
+                let downlink = serde_json::from_slice::<InjectedDownlink>(&publish.payload)
+                    .map_err(Error::from)
+                    .and_then(Packet::try_from);
+                match downlink {
+                    Ok(packet) => {
+                        info!(logger, "injecting downlink {packet}");
+                        if let Err(err) = downlinks.inject(packet).await {
+                            warn!(logger, "failed to inject downlink: {err:?}");
+                        }
+                    }
+                    Err(err) => warn!(logger, "ignoring invalid downlink: {err:?}"),
+                }
+            }
+            Ok(_) => (),
+            Err(err) => {
+                warn!(logger, "connection error: {err:?}");
+                tokio::select! {
+                    _ = shutdown.clone() => (),
+                    _ = time::sleep(MQTT_RECONNECT_WAIT) => (),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mirror::StatusEvent;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    #[test]
+    fn injected_downlink() {
+        let json = r#"{"tmst": 1000000, "freq": 923.3, "datr": "SF10BW500", "data": "AQID"}"#;
+        let downlink: InjectedDownlink = serde_json::from_str(json).expect("downlink");
+        let packet = Packet::try_from(downlink).expect("packet");
+        assert_eq!(1000000, packet.timestamp);
+        assert_eq!("SF10BW500", packet.datarate);
+        assert_eq!(&[1, 2, 3], packet.payload());
+    }
+
+    /// Reads an MQTT packet, returning its type and flags byte and its body
+    async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let header = stream.read_u8().await.expect("packet header");
+        let (mut length, mut shift) = (0usize, 0);
+        loop {
+            let byte = stream.read_u8().await.expect("packet length");
+            length |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).await.expect("packet body");
+        (header, body)
+    }
+
+    /// Splits a length prefixed topic off the start of the given body
+    fn split_topic(body: &[u8]) -> (String, &[u8]) {
+        let length = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let topic = String::from_utf8(body[2..2 + length].to_vec()).expect("topic");
+        (topic, &body[2 + length..])
+    }
+
+    /// Runs a minimal in-process broker for a single client. Every
+    /// subscription is answered with the given publish on the subscribed
+    /// topic. Returns the topic and payload of the first publish received
+    /// from the client.
+    async fn run_broker(listener: TcpListener, inject: &[u8]) -> (String, Vec<u8>) {
+        let (mut stream, _) = listener.accept().await.expect("client");
+        loop {
+            let (header, body) = read_packet(&mut stream).await;
+            match header >> 4 {
+                // CONNECT
+                1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await,
+                // PUBLISH
+                3 => {
+                    let (topic, payload) = split_topic(&body);
+                    return (topic, payload.to_vec());
+                }
+                // SUBSCRIBE
+                8 => {
+                    let (topic, _) = split_topic(&body[2..]);
+                    let mut packets = vec![0x90, 0x03, body[0], body[1], 0x00];
+                    let length = 2 + topic.len() + inject.len();
+                    assert!(length < 128);
+                    packets.extend_from_slice(&[0x30, length as u8, 0, topic.len() as u8]);
+                    packets.extend_from_slice(topic.as_bytes());
+                    packets.extend_from_slice(inject);
+                    stream.write_all(&packets).await
+                }
+                // PINGREQ
+                12 => stream.write_all(&[0xd0, 0x00]).await,
+                _ => Ok(()),
+            }
+            .expect("broker write");
+        }
+    }
+
+    #[tokio::test]
+    async fn broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("listener");
+        let uri: Uri = format!("mqtt://{}", listener.local_addr().expect("broker addr"))
+            .parse()
+            .expect("broker uri");
+        let inject = br#"{"tmst": 1000000, "freq": 923.3, "datr": "SF10BW500", "data": "AQID"}"#;
+        let broker = tokio::spawn(async move { run_broker(listener, inject).await });
+
+        let (downlinks, mut injected) = gateway::message_channel(1);
+        let (trigger, shutdown) = triggered::trigger();
+        let mut sink = MqttSink::new(
+            &uri,
+            "gateway",
+            "test".to_string(),
+            None,
+            downlinks,
+            shutdown,
+        )
+        .expect("sink");
+
+        // Downlinks published to the inject topic are passed to the gateway
+        match time::timeout(Duration::from_secs(5), injected.recv()).await {
+            Ok(Some(gateway::Message::Inject(packet))) => {
+                assert_eq!(1000000, packet.timestamp);
+                assert_eq!(&[1, 2, 3], packet.payload());
+            }
+            other => panic!("unexpected injection {other:?}"),
+        }
+
+        // Records are published to their topic
+        let record = Record::Status {
+            time: 0,
+            event: StatusEvent::ForwarderConnected {
+                mac: "0000000000000000".to_string(),
+            },
+        };
+        sink.send(&record).expect("send");
+        let (topic, payload) = time::timeout(Duration::from_secs(5), broker)
+            .await
+            .expect("published record")
+            .expect("broker");
+        assert_eq!(format!("gateway/{}", record.kind()), topic);
+        assert_eq!(serde_json::to_vec(&record).expect("record json"), payload);
+        trigger.trigger();
+    }
+}
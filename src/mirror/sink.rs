@@ -0,0 +1,144 @@
+use super::{mqtt::MqttSink, webhook::WebhookSink, Record};
+use crate::{gateway, settings::MirrorSettings, Result, Settings};
+use rand::{rngs::OsRng, RngCore};
+use serde_json::json;
+use std::{fmt, path::PathBuf};
+use tokio::{fs, io::AsyncWriteExt, net::UdpSocket};
+
+const SEMTECH_PROTOCOL_VERSION: u8 = 2;
+const SEMTECH_PUSH_DATA: u8 = 0;
+
+/// A destination for mirrored records.
+pub enum Sink {
+    /// Sends uplinks as semtech PUSH_DATA packets to an upstream UDP server.
+    /// Downlinks are not mirrored to this sink.
+    Udp {
+        socket: UdpSocket,
+        addr: String,
+        gateway_eui: u64,
+    },
+    /// Posts each record as JSON to a webhook
+    Http(WebhookSink),
+    /// Appends each record as a line of JSON to a file
+    File { path: PathBuf, file: fs::File },
+    /// Publishes each record to an MQTT broker
+    Mqtt(MqttSink),
+}
+
+impl fmt::Display for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Udp { addr, .. } => write!(f, "udp://{addr}"),
+            Self::Http(sink) => sink.fmt(f),
+            Self::File { path, .. } => write!(f, "file://{}", path.display()),
+            Self::Mqtt(sink) => sink.fmt(f),
+        }
+    }
+}
+
+impl Sink {
+    pub async fn new(
+        settings: &MirrorSettings,
+        gateway_settings: &Settings,
+        downlinks: gateway::MessageSender,
+        shutdown: &triggered::Listener,
+    ) -> Result<Self> {
+        let sink = match settings {
+            MirrorSettings::Udp { addr, gateway_eui } => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(addr).await?;
+                Self::Udp {
+                    socket,
+                    addr: addr.clone(),
+                    gateway_eui: *gateway_eui,
+                }
+            }
+            MirrorSettings::Http { uri } => Self::Http(WebhookSink::new(uri)),
+            MirrorSettings::File { path } => {
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                Self::File {
+                    path: path.clone(),
+                    file,
+                }
+            }
+            MirrorSettings::Mqtt {
+                uri,
+                topic,
+                username,
+                password,
+            } => {
+                let client_id = gateway_settings.keypair.public_key().to_string();
+                let credentials = username.clone().zip(password.clone());
+                Self::Mqtt(MqttSink::new(
+                    uri,
+                    topic,
+                    client_id,
+                    credentials,
+                    downlinks,
+                    shutdown.clone(),
+                )?)
+            }
+        };
+        Ok(sink)
+    }
+
+    pub async fn send(&mut self, record: &Record) -> Result {
+        match self {
+            Self::Udp {
+                socket,
+                gateway_eui,
+                ..
+            } => {
+                if let Some(push_data) = push_data(record, *gateway_eui)? {
+                    socket.send(&push_data).await?;
+                }
+                Ok(())
+            }
+            Self::Http(sink) => sink.send(record),
+            Self::File { file, .. } => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                file.write_all(&line).await?;
+                file.flush().await?;
+                Ok(())
+            }
+            Self::Mqtt(sink) => sink.send(record),
+        }
+    }
+}
+
+/// Encodes an uplink record as a semtech UDP PUSH_DATA packet. Returns None
+/// for records that are not uplinks.
+fn push_data(record: &Record, gateway_eui: u64) -> Result<Option<Vec<u8>>> {
+    let packet = match record {
+        Record::Uplink { packet, .. } => packet,
+        _ => return Ok(None),
+    };
+    let rxpk = json!({
+        "rxpk": [{
+            "tmst": packet.tmst as u32,
+            "freq": packet.freq,
+            "chan": 0,
+            "rfch": 0,
+            "stat": 1,
+            "modu": "LORA",
+            "datr": packet.datr,
+            "codr": "4/5",
+            "rssi": packet.rssi as i32,
+            "lsnr": packet.lsnr,
+            "size": packet.size,
+            "data": packet.data,
+        }]
+    });
+    let mut buf = Vec::with_capacity(256);
+    buf.push(SEMTECH_PROTOCOL_VERSION);
+    buf.extend_from_slice(&(OsRng.next_u32() as u16).to_be_bytes());
+    buf.push(SEMTECH_PUSH_DATA);
+    buf.extend_from_slice(&gateway_eui.to_be_bytes());
+    serde_json::to_writer(&mut buf, &rxpk)?;
+    Ok(Some(buf))
+}
@@ -0,0 +1,110 @@
+use super::{Record, RoutingRecord};
+use crate::{Error, Result};
+
+/// The kinds of records that can be tapped
+pub const KINDS: &[&str] = &["uplink", "downlink", "beacon", "witness", "status"];
+
+/// Selects the records streamed to a packet tap. Records of packets are
+/// selected by the devaddr or DevEUI in their routing information.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    devaddr: Option<String>,
+    dev_eui: Option<String>,
+    kinds: Vec<String>,
+}
+
+impl RecordFilter {
+    /// Constructs a filter from a hex devaddr and DevEUI and a list of record
+    /// kinds. Empty values select all records.
+    pub fn new(devaddr: &str, dev_eui: &str, kinds: &[String]) -> Result<Self> {
+        let devaddr = match devaddr {
+            "" => None,
+            devaddr => u32::from_str_radix(devaddr, 16)
+                .map(|devaddr| format!("{devaddr:08X}"))
+                .map(Some)
+                .map_err(|_| Error::custom(format!("invalid devaddr: {devaddr}")))?,
+        };
+        let dev_eui = match dev_eui {
+            "" => None,
+            dev_eui => u64::from_str_radix(dev_eui, 16)
+                .map(|dev_eui| format!("{dev_eui:016X}"))
+                .map(Some)
+                .map_err(|_| Error::custom(format!("invalid dev_eui: {dev_eui}")))?,
+        };
+        if let Some(kind) = kinds.iter().find(|kind| !KINDS.contains(&kind.as_str())) {
+            return Err(Error::custom(format!("invalid packet type: {kind}")));
+        }
+        Ok(Self {
+            devaddr,
+            dev_eui,
+            kinds: kinds.to_vec(),
+        })
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|kind| kind == record.kind()) {
+            return false;
+        }
+        if self.devaddr.is_none() && self.dev_eui.is_none() {
+            return true;
+        }
+        let routing = match record {
+            Record::Uplink { packet, .. }
+            | Record::Downlink { packet, .. }
+            | Record::Witness { packet } => packet.routing.as_ref(),
+            _ => None,
+        };
+        match routing {
+            Some(RoutingRecord::Devaddr(devaddr)) => self.devaddr.as_ref() == Some(devaddr),
+            Some(RoutingRecord::Eui { dev_eui, .. }) => self.dev_eui.as_ref() == Some(dev_eui),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mirror::PacketRecord;
+
+    fn uplink(routing: RoutingRecord) -> Record {
+        Record::Uplink {
+            packet: PacketRecord {
+                time: 0,
+                tmst: 0,
+                freq: 868.1,
+                datr: "SF7BW125".to_string(),
+                rssi: -80.0,
+                lsnr: 5.5,
+                size: 0,
+                data: String::new(),
+                routing: Some(routing),
+            },
+            ouis: vec![1],
+            default_routers: vec![],
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let devaddr = uplink(RoutingRecord::Devaddr("48000123".to_string()));
+        let join = uplink(RoutingRecord::Eui {
+            dev_eui: "0000000000000001".to_string(),
+            app_eui: "0000000000000002".to_string(),
+        });
+
+        let filter = RecordFilter::new("48000123", "", &[]).expect("filter");
+        assert!(filter.matches(&devaddr));
+        assert!(!filter.matches(&join));
+
+        let filter = RecordFilter::new("", "1", &["uplink".to_string()]).expect("filter");
+        assert!(!filter.matches(&devaddr));
+        assert!(filter.matches(&join));
+
+        let filter = RecordFilter::new("", "", &["downlink".to_string()]).expect("filter");
+        assert!(!filter.matches(&devaddr));
+
+        assert!(RecordFilter::new("", "", &["packet".to_string()]).is_err());
+        assert!(RecordFilter::new("xyz", "", &[]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use super::Record;
+use crate::{curl, Result};
+use http::Uri;
+use slog::{info, o, warn, Logger};
+use std::fmt;
+use tokio::sync::mpsc;
+
+const WEBHOOK_QUEUE_SIZE: usize = 20;
+
+/// Posts records to a webhook from a background task so a slow or
+/// unreachable webhook does not hold up the other sinks.
+pub struct WebhookSink {
+    uri: Uri,
+    queue: mpsc::Sender<String>,
+    /// Records dropped since the queue last accepted one
+    dropped: u64,
+    logger: Logger,
+}
+
+impl fmt::Display for WebhookSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.uri)
+    }
+}
+
+impl WebhookSink {
+    pub fn new(uri: &Uri) -> Self {
+        let (queue, bodies) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+        // We start the webhook scope at the root logger to avoid picking up
+        // the previously set KV pairs (which causes dupes)
+        let logger = slog_scope::logger().new(o!(
+            "module" => "webhook",
+            "uri" => uri.to_string(),
+        ));
+        tokio::spawn(run_posts(uri.to_string(), bodies, logger.clone()));
+        Self {
+            uri: uri.clone(),
+            queue,
+            dropped: 0,
+            logger,
+        }
+    }
+
+    /// Queues the record for posting without waiting. Records are dropped
+    /// while the queue is full, for example while the webhook can not be
+    /// reached.
+    pub fn send(&mut self, record: &Record) -> Result {
+        let body = serde_json::to_string(record)?;
+        match self.queue.try_send(body) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    info!(self.logger, "resumed posting";
+                        "dropped" => self.dropped);
+                    self.dropped = 0;
+                }
+            }
+            Err(err) => {
+                if self.dropped == 0 {
+                    warn!(self.logger, "dropping records: {err:?}");
+                }
+                self.dropped += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn run_posts(uri: String, mut bodies: mpsc::Receiver<String>, logger: Logger) {
+    while let Some(body) = bodies.recv().await {
+        if let Err(err) = curl::post(&uri, body, None, |_| Ok(())).await {
+            warn!(logger, "failed to post record: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mirror::StatusEvent;
+    use hyper::{
+        body,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use std::{convert::Infallible, time::Duration};
+    use tokio::time;
+
+    #[tokio::test]
+    async fn posts_records() {
+        // A local webhook that passes the bodies it receives on
+        let (bodies, mut received) = mpsc::channel(1);
+        let make_service = make_service_fn(move |_| {
+            let bodies = bodies.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let bodies = bodies.clone();
+                    async move {
+                        let body = body::to_bytes(request.into_body()).await?;
+                        let _ = bodies.send(body.to_vec()).await;
+                        Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let uri: Uri = format!("http://{}/records", server.local_addr())
+            .parse()
+            .expect("webhook uri");
+        tokio::spawn(server);
+
+        let mut sink = WebhookSink::new(&uri);
+        let record = Record::Status {
+            time: 0,
+            event: StatusEvent::ForwarderConnected {
+                mac: "0000000000000000".to_string(),
+            },
+        };
+        sink.send(&record).expect("send");
+        let body = time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("posted record")
+            .expect("record body");
+        assert_eq!(serde_json::to_vec(&record).expect("record json"), body);
+    }
+}
@@ -0,0 +1,194 @@
+//! Outbound connections through an HTTP CONNECT or SOCKS5 proxy.
+
+use crate::{settings::ProxySettings, Error, Result};
+use http::Uri;
+use std::net::IpAddr;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Limit on the size of the response header to an HTTP CONNECT request
+const MAX_RESPONSE_HEADER: usize = 8192;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Http,
+    Socks5,
+}
+
+fn kind(proxy: &ProxySettings) -> Result<Kind> {
+    match proxy.uri.scheme_str() {
+        Some("http") => Ok(Kind::Http),
+        Some("socks5" | "socks5h") => Ok(Kind::Socks5),
+        _ => Err(Error::custom(format!(
+            "unsupported proxy uri {}",
+            proxy.uri
+        ))),
+    }
+}
+
+/// Returns the host and port of a uri, using the default port of its scheme
+/// when none is given.
+fn host_port(uri: &Uri) -> Result<(&str, u16)> {
+    let host = uri
+        .host()
+        .ok_or_else(|| Error::custom(format!("missing host in {uri}")))?;
+    let port = match (uri.port_u16(), uri.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("https")) => 443,
+        (None, Some("socks5" | "socks5h")) => 1080,
+        (None, _) => 80,
+    };
+    Ok((host, port))
+}
+
+/// Opens a tcp connection to the host and port of the given uri through the
+/// proxy. Host names are resolved by the proxy.
+pub async fn connect(proxy: &ProxySettings, target: &Uri) -> Result<TcpStream> {
+    let kind = kind(proxy)?;
+    let (host, port) = host_port(target)?;
+    let (proxy_host, proxy_port) = host_port(&proxy.uri)?;
+    let mut stream = TcpStream::connect((unbracket(proxy_host), proxy_port)).await?;
+    match kind {
+        Kind::Http => http_connect(&mut stream, host, port).await?,
+        Kind::Socks5 => socks5_connect(&mut stream, unbracket(host), port).await?,
+    }
+    Ok(stream)
+}
+
+/// The proxy uri in the form expected by curl's --proxy option
+pub fn curl_uri(proxy: &ProxySettings) -> String {
+    match proxy.uri.scheme_str() {
+        // Have the proxy resolve host names, as for gRPC connections
+        Some("socks5") => proxy.uri.to_string().replacen("socks5", "socks5h", 1),
+        _ => proxy.uri.to_string(),
+    }
+}
+
+/// Strips the brackets around an IPv6 address host
+fn unbracket(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+async fn http_connect<S>(stream: &mut S, host: &str, port: u16) -> Result
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    // Read byte by byte to not consume any data following the header
+    let mut response = Vec::with_capacity(128);
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_HEADER {
+            return Err(Error::custom("proxy response header too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(Error::custom(format!(
+            "proxy connect to {host}:{port} failed: {status_line}"
+        ))),
+    }
+}
+
+async fn socks5_connect<S>(stream: &mut S, host: &str, port: u16) -> Result
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTH]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [SOCKS_VERSION, SOCKS_NO_AUTH] {
+        return Err(Error::custom("socks5 proxy requires authentication"));
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => {
+            request.push(SOCKS_IPV4);
+            request.extend(addr.octets());
+        }
+        Ok(IpAddr::V6(addr)) => {
+            request.push(SOCKS_IPV6);
+            request.extend(addr.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| Error::custom(format!("host name too long: {host}")))?;
+            request.push(SOCKS_DOMAIN);
+            request.push(len);
+            request.extend(host.as_bytes());
+        }
+    }
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(Error::custom(format!(
+            "socks5 connect to {host}:{port} failed with code {}",
+            reply[1]
+        )));
+    }
+    // Skip the bound address and port
+    let addr_len = match reply[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        SOCKS_DOMAIN => stream.read_u8().await? as usize,
+        other => {
+            return Err(Error::custom(format!(
+                "invalid socks5 address type {other}"
+            )))
+        }
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn proxy(uri: &str) -> ProxySettings {
+        ProxySettings {
+            uri: Uri::from_str(uri).expect("uri"),
+        }
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(
+            Kind::Http,
+            kind(&proxy("http://10.0.0.1:3128")).expect("kind")
+        );
+        assert_eq!(
+            Kind::Socks5,
+            kind(&proxy("socks5://10.0.0.1")).expect("kind")
+        );
+        assert!(kind(&proxy("https://10.0.0.1")).is_err());
+        assert!(curl_uri(&proxy("socks5://10.0.0.1:1080")).starts_with("socks5h://10.0.0.1:1080"));
+
+        let uri = Uri::from_str("https://validator.example.com").expect("uri");
+        assert_eq!(
+            ("validator.example.com", 443),
+            host_port(&uri).expect("host")
+        );
+        let uri = Uri::from_str("socks5://[::1]").expect("uri");
+        let (host, port) = host_port(&uri).expect("host");
+        assert_eq!(("::1", 1080), (unbracket(host), port));
+    }
+}
@@ -1,11 +1,12 @@
 use crate::{error::RegionError, Error, Result};
+use config::{Config, File};
 use helium_proto::{
-    BlockchainRegionParamV1, GatewayRegionParamsRespV1, GatewayRegionParamsStreamedRespV1,
-    Region as ProtoRegion,
+    BlockchainRegionParamV1, BlockchainRegionSpreadingV1, GatewayRegionParamsRespV1,
+    GatewayRegionParamsStreamedRespV1, Region as ProtoRegion, RegionSpreading, TaggedSpreading,
 };
 use rust_decimal::Decimal;
 use serde::{de, Deserialize, Deserializer};
-use std::{fmt, str::FromStr};
+use std::{fmt, path::Path, str::FromStr};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Region(ProtoRegion);
@@ -128,7 +129,107 @@ impl TryFrom<GatewayRegionParamsRespV1> for RegionParams {
     }
 }
 
+/// A channel plan as read from a local region params file
+#[derive(Debug, Deserialize)]
+struct ChannelPlan {
+    /// Defaults to the configured region
+    region: Option<Region>,
+    /// Antenna gain in dBi
+    #[serde(default)]
+    gain: f64,
+    channels: Vec<ChannelPlanEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelPlanEntry {
+    /// Frequency in Hz
+    frequency: u64,
+    /// Bandwidth in Hz
+    bandwidth: u32,
+    /// Maximum EIRP in dBm
+    max_eirp: f64,
+    spreading_factors: Vec<u8>,
+    /// Maximum packet size for all spreading factors
+    #[serde(default = "default_max_packet_size")]
+    max_packet_size: u32,
+}
+
+fn default_max_packet_size() -> u32 {
+    242
+}
+
+fn region_spreading(spreading_factor: u8) -> Result<RegionSpreading> {
+    match spreading_factor {
+        7 => Ok(RegionSpreading::Sf7),
+        8 => Ok(RegionSpreading::Sf8),
+        9 => Ok(RegionSpreading::Sf9),
+        10 => Ok(RegionSpreading::Sf10),
+        11 => Ok(RegionSpreading::Sf11),
+        12 => Ok(RegionSpreading::Sf12),
+        other => Err(Error::custom(format!("invalid spreading factor {other}"))),
+    }
+}
+
+/// The spreading factor of a region spreading
+fn spreading_factor(region_spreading: i32) -> Option<u8> {
+    match RegionSpreading::from_i32(region_spreading)? {
+        RegionSpreading::Sf7 => Some(7),
+        RegionSpreading::Sf8 => Some(8),
+        RegionSpreading::Sf9 => Some(9),
+        RegionSpreading::Sf10 => Some(10),
+        RegionSpreading::Sf11 => Some(11),
+        RegionSpreading::Sf12 => Some(12),
+        _ => None,
+    }
+}
+
+/// Converts a dB value to the tenths used in region params
+fn tenths(value: f64) -> i64 {
+    (value * 10.0).round() as i64
+}
+
 impl RegionParams {
+    /// Reads region params from a TOML or JSON channel plan file, using the
+    /// given region when the file does not specify one.
+    pub fn from_file(path: &Path, region: Region) -> Result<Self> {
+        let plan: ChannelPlan = Config::builder()
+            .add_source(File::from(path))
+            .build()
+            .and_then(|config| config.try_deserialize())?;
+        if plan.channels.is_empty() {
+            return Err(RegionError::no_region_params());
+        }
+        let params = plan
+            .channels
+            .iter()
+            .map(|channel| {
+                let tagged_spreading = channel
+                    .spreading_factors
+                    .iter()
+                    .map(|spreading_factor| {
+                        region_spreading(*spreading_factor).map(|spreading| TaggedSpreading {
+                            region_spreading: spreading.into(),
+                            max_packet_size: channel.max_packet_size,
+                        })
+                    })
+                    .collect::<Result<Vec<TaggedSpreading>>>()?;
+                Ok(BlockchainRegionParamV1 {
+                    channel_frequency: channel.frequency,
+                    bandwidth: channel.bandwidth,
+                    max_eirp: u32::try_from(tenths(channel.max_eirp)).map_err(|_| {
+                        Error::custom(format!("invalid max eirp {}", channel.max_eirp))
+                    })?,
+                    spreading: Some(BlockchainRegionSpreadingV1 { tagged_spreading }),
+                })
+            })
+            .collect::<Result<Vec<BlockchainRegionParamV1>>>()?;
+        Ok(Self {
+            gain: Decimal::new(tenths(plan.gain), 1),
+            region: plan.region.unwrap_or(region),
+            params,
+        })
+    }
+
     pub fn max_eirp(&self) -> Option<Decimal> {
         self.params
             .iter()
@@ -142,6 +243,51 @@ impl RegionParams {
             .and_then(|max_eirp| (max_eirp - self.gain).trunc().to_u32())
     }
 
+    /// The tx power for the channel with the given frequency in Hz, or None
+    /// when the frequency is not a channel of the region
+    pub fn channel_tx_power(&self, frequency: u64) -> Option<u32> {
+        use rust_decimal::prelude::ToPrimitive;
+        self.params
+            .iter()
+            .find(|p| p.channel_frequency == frequency)
+            .and_then(|p| {
+                (Decimal::new(p.max_eirp as i64, 1) - self.gain)
+                    .trunc()
+                    .to_u32()
+            })
+    }
+
+    /// Checks that the given datarate, like "SF10BW125", is one of the
+    /// datarates of the channel with the given frequency in Hz
+    pub fn check_datarate(&self, frequency: u64, datarate: &str) -> Result {
+        let channel = self
+            .params
+            .iter()
+            .find(|p| p.channel_frequency == frequency)
+            .ok_or_else(|| {
+                Error::custom(format!(
+                    "frequency {frequency} is not a channel of region {}",
+                    self.region
+                ))
+            })?;
+        let bandwidth = channel.bandwidth / 1000;
+        let valid = channel
+            .spreading
+            .iter()
+            .flat_map(|spreading| spreading.tagged_spreading.iter())
+            .filter_map(|tagged| spreading_factor(tagged.region_spreading))
+            .any(|spreading_factor| {
+                format!("SF{spreading_factor}BW{bandwidth}").eq_ignore_ascii_case(datarate)
+            });
+        if !valid {
+            return Err(Error::custom(format!(
+                "datarate {datarate} is not valid for channel {frequency} of region {}",
+                self.region
+            )));
+        }
+        Ok(())
+    }
+
     pub fn to_string(v: &Option<Self>) -> String {
         match v {
             None => "none".to_string(),
@@ -149,3 +295,60 @@ impl RegionParams {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn channel_plan() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("channel_plan.toml");
+        fs::write(
+            &path,
+            r#"
+            gain = 1.2
+            [[channels]]
+            frequency = 868100000
+            bandwidth = 125000
+            max_eirp = 16.0
+            spreading_factors = [7, 12]
+            "#,
+        )
+        .expect("channel plan");
+        let region = Region(ProtoRegion::Eu868);
+        let params = RegionParams::from_file(&path, region).expect("region params");
+        assert_eq!("EU868", params.region.to_string());
+        assert_eq!(Decimal::new(12, 1), params.gain);
+        assert_eq!(160, params.params[0].max_eirp);
+        assert_eq!(Some(14), params.tx_power());
+        assert_eq!(Some(14), params.channel_tx_power(868100000));
+        assert_eq!(None, params.channel_tx_power(868300000));
+        let spreading = params.params[0].spreading.as_ref().expect("spreading");
+        assert_eq!(2, spreading.tagged_spreading.len());
+    }
+
+    #[test]
+    fn check_datarate() {
+        let params = RegionParams {
+            gain: Decimal::new(0, 1),
+            region: Region(ProtoRegion::Eu868),
+            params: vec![BlockchainRegionParamV1 {
+                channel_frequency: 868100000,
+                bandwidth: 125000,
+                max_eirp: 160,
+                spreading: Some(BlockchainRegionSpreadingV1 {
+                    tagged_spreading: vec![TaggedSpreading {
+                        region_spreading: RegionSpreading::Sf12.into(),
+                        max_packet_size: 51,
+                    }],
+                }),
+            }],
+        };
+        assert!(params.check_datarate(868100000, "SF12BW125").is_ok());
+        assert!(params.check_datarate(868100000, "SF7BW125").is_err());
+        assert!(params.check_datarate(868100000, "SF12BW500").is_err());
+        assert!(params.check_datarate(868300000, "SF12BW125").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Reloads the settings of the running service on SIGHUP or a local API
+//! request. Changes to settings that the running tasks can pick up are applied
+//! right away, other changes are reported as requiring a restart.
+
+use crate::{
+    beaconer,
+    router::dispatcher,
+    settings::{log_level, Settings},
+    sync, updater, Result,
+};
+use slog::{info, o, warn, Logger};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+#[derive(Debug)]
+pub enum Message {
+    Reload {
+        response: sync::ResponseSender<Result<ReloadReport>>,
+    },
+}
+
+pub type MessageSender = sync::MessageSender<Message>;
+pub type MessageReceiver = sync::MessageReceiver<Message>;
+
+pub fn message_channel(size: usize) -> (MessageSender, MessageReceiver) {
+    sync::message_channel(size)
+}
+
+impl MessageSender {
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        let (tx, rx) = sync::response_channel();
+        let _ = self.0.send(Message::Reload { response: tx }).await;
+        rx.recv().await?
+    }
+}
+
+/// The names of the settings that changed in a reload
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Changed settings that were applied to the running service
+    pub applied: Vec<String>,
+    /// Changed settings that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadReport {
+    /// Compares the running settings with reloaded ones
+    pub fn new(current: &Settings, new: &Settings) -> Self {
+        let mut report = Self::default();
+        // Settings the running service picks up
+        report.compare("log.level", &current.log.level, &new.log.level, true);
+        report.compare("routers", &current.routers, &new.routers, true);
+        report.compare(
+            "default_router_policy",
+            &current.default_router_policy,
+            &new.default_router_policy,
+            true,
+        );
+        report.compare("cache", &current.cache, &new.cache, true);
+        report.compare("update", &current.update, &new.update, true);
+        report.compare("poc", &current.poc, &new.poc, true);
+        // Settings the service is constructed with
+        report.compare("listen", &current.listen, &new.listen, false);
+        report.compare("api", &current.api, &new.api, false);
+        report.compare("api_socket", &current.api_socket, &new.api_socket, false);
+        report.compare("http_api", &current.http_api, &new.http_api, false);
+        report.compare("signing", &current.signing, &new.signing, false);
+        report.compare(
+            "keypair",
+            current.keypair.public_key(),
+            new.keypair.public_key(),
+            false,
+        );
+        report.compare("onboarding", &current.onboarding, &new.onboarding, false);
+        report.compare("region", &current.region, &new.region, false);
+        report.compare(
+            "region_params",
+            &current.region_params,
+            &new.region_params,
+            false,
+        );
+        report.compare("log.method", &current.log.method, &new.log.method, false);
+        report.compare(
+            "log.timestamp",
+            &current.log.timestamp,
+            &new.log.timestamp,
+            false,
+        );
+        report.compare("gateways", &current.gateways, &new.gateways, false);
+        report.compare("seeds", &current.seeds, &new.seeds, false);
+        report.compare(
+            "downlink_policy",
+            &current.downlink_policy,
+            &new.downlink_policy,
+            false,
+        );
+        report.compare(
+            "static_routes",
+            &current.static_routes,
+            &new.static_routes,
+            false,
+        );
+        report.compare("tls", &current.tls, &new.tls, false);
+        report.compare("proxy", &current.proxy, &new.proxy, false);
+        report.compare("accounting", &current.accounting, &new.accounting, false);
+        report.compare("mirrors", &current.mirrors, &new.mirrors, false);
+        report.compare("roaming", &current.roaming, &new.roaming, false);
+        report
+    }
+
+    /// Records the setting with the given name when it changed. Settings are
+    /// compared by their debug representation since not all of them
+    /// implement PartialEq.
+    fn compare<T: fmt::Debug>(&mut self, name: &str, current: &T, new: &T, reloadable: bool) {
+        if format!("{current:?}") == format!("{new:?}") {
+            return;
+        }
+        if reloadable {
+            self.applied.push(name.to_string());
+        } else {
+            self.restart_required.push(name.to_string());
+        }
+    }
+
+    fn is_applied(&self, name: &str) -> bool {
+        self.applied.iter().any(|applied| applied == name)
+    }
+}
+
+pub struct Reloader {
+    /// The configuration folder to reload settings from
+    config: PathBuf,
+    /// The settings the running service uses
+    settings: Settings,
+    messages: MessageReceiver,
+    dispatcher: dispatcher::MessageSender,
+    beaconer: beaconer::MessageSender,
+    updater: updater::MessageSender,
+}
+
+impl Reloader {
+    pub fn new(
+        config: &Path,
+        settings: &Settings,
+        messages: MessageReceiver,
+        dispatcher: dispatcher::MessageSender,
+        beaconer: beaconer::MessageSender,
+        updater: updater::MessageSender,
+    ) -> Self {
+        Self {
+            config: config.to_path_buf(),
+            settings: settings.clone(),
+            messages,
+            dispatcher,
+            beaconer,
+            updater,
+        }
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "reload"));
+        info!(logger, "starting");
+        let mut hangup = signal(SignalKind::hangup())?;
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                _ = hangup.recv() => {
+                    info!(logger, "reloading settings on hangup");
+                    let _ = self.reload(&logger).await;
+                },
+                message = self.messages.recv() => match message {
+                    Some(Message::Reload { response }) => {
+                        info!(logger, "reloading settings on request");
+                        let reply = self.reload(&logger).await;
+                        response.send(reply, &logger)
+                    },
+                    None => warn!(logger, "ignoring closed message channel"),
+                }
+            }
+        }
+    }
+
+    async fn reload(&mut self, logger: &Logger) -> Result<ReloadReport> {
+        let settings = Settings::new(&self.config).map_err(|err| {
+            warn!(logger, "failed to reload settings: {err}");
+            err
+        })?;
+        let report = ReloadReport::new(&self.settings, &settings);
+
+        if report.is_applied("log.level") {
+            log_level::set_runtime_level(settings.log.level);
+            self.settings.log.level = settings.log.level;
+        }
+        if report.is_applied("routers")
+            || report.is_applied("default_router_policy")
+            || report.is_applied("cache")
+        {
+            self.dispatcher.settings_changed(&settings).await;
+            self.settings.routers = settings.routers.clone();
+            self.settings.default_router_policy = settings.default_router_policy;
+            self.settings.cache = settings.cache.clone();
+        }
+        if report.is_applied("poc") {
+            self.beaconer.settings_changed(&settings).await;
+            self.settings.poc = settings.poc.clone();
+        }
+        if report.is_applied("update") {
+            self.updater.settings_changed(&settings).await;
+            self.settings.update = settings.update.clone();
+        }
+
+        info!(logger, "reloaded settings";
+            "applied" => report.applied.join(","),
+            "restart_required" => report.restart_required.join(","));
+        Ok(report)
+    }
+}
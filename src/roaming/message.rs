@@ -0,0 +1,400 @@
+//! JSON messages of the LoRaWAN Backend Interfaces specification used for
+//! passive roaming.
+
+use crate::{Error, Packet, Region, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use helium_proto::{packet::PacketType, routing_information::Data as RoutingData, WindowV1};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+pub const PROTOCOL_VERSION: &str = "1.1";
+pub const RESULT_SUCCESS: &str = "Success";
+pub const PR_START_REQ: &str = "PRStartReq";
+pub const XMIT_DATA_REQ: &str = "XmitDataReq";
+
+/// Default delay of the first receive window of data downlinks in seconds
+const RECEIVE_DELAY1: u64 = 1;
+/// Delay of the first receive window of join accepts in seconds
+const JOIN_ACCEPT_DELAY1: u64 = 5;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UplinkRequest {
+    pub protocol_version: &'static str,
+    #[serde(rename = "SenderID")]
+    pub sender_id: String,
+    #[serde(rename = "ReceiverID")]
+    pub receiver_id: String,
+    #[serde(rename = "TransactionID")]
+    pub transaction_id: u32,
+    pub message_type: &'static str,
+    #[serde(rename = "PHYPayload")]
+    pub phy_payload: String,
+    #[serde(rename = "ULMetaData")]
+    pub ul_meta_data: ULMetaData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ULMetaData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev_addr: Option<String>,
+    #[serde(rename = "DevEUI", skip_serializing_if = "Option::is_none")]
+    pub dev_eui: Option<String>,
+    pub data_rate: Option<u8>,
+    #[serde(rename = "ULFreq")]
+    pub ul_freq: f32,
+    pub recv_time: String,
+    #[serde(rename = "RFRegion")]
+    pub rf_region: String,
+    pub gw_cnt: u32,
+    #[serde(rename = "GWInfo")]
+    pub gw_info: Vec<GWInfo>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GWInfo {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "RFRegion")]
+    pub rf_region: String,
+    #[serde(rename = "RSSI")]
+    pub rssi: i32,
+    #[serde(rename = "SNR")]
+    pub snr: f32,
+    #[serde(rename = "DLAllowed")]
+    pub dl_allowed: bool,
+}
+
+/// The answer to a PRStartReq or XmitDataReq. Only the fields needed to
+/// schedule a downlink are decoded.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Answer {
+    pub result: AnswerResult,
+    #[serde(rename = "PHYPayload", default)]
+    pub phy_payload: Option<String>,
+    #[serde(rename = "DLMetaData", default)]
+    pub dl_meta_data: Option<DLMetaData>,
+    /// Lifetime of the passive roaming session in seconds, only included in
+    /// a PRStartAns. A missing or zero lifetime means the partner is
+    /// stateless and expects a PRStartReq for every uplink.
+    #[serde(default)]
+    pub lifetime: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AnswerResult {
+    pub result_code: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DLMetaData {
+    #[serde(rename = "DLFreq1")]
+    pub dl_freq_1: Option<f32>,
+    #[serde(rename = "DataRate1")]
+    pub data_rate_1: Option<u8>,
+    /// Only used for data downlinks, join accepts are always sent after
+    /// JoinAcceptDelay1
+    #[serde(rename = "RXDelay1", default)]
+    pub rx_delay_1: Option<u64>,
+    #[serde(rename = "DLFreq2")]
+    pub dl_freq_2: Option<f32>,
+    #[serde(rename = "DataRate2")]
+    pub data_rate_2: Option<u8>,
+}
+
+impl Answer {
+    pub fn is_success(&self) -> bool {
+        self.result.result_code == RESULT_SUCCESS
+    }
+
+    /// Returns the roaming session lifetime granted by a PRStartAns.
+    pub fn lifetime(&self) -> Duration {
+        Duration::from_secs(self.lifetime.unwrap_or(0))
+    }
+
+    /// Constructs the downlink packet for this answer, if any, given the
+    /// uplink it is an answer to.
+    pub fn to_downlink(&self, uplink: &Packet, region: &Region) -> Result<Option<Packet>> {
+        if !self.is_success() {
+            return Err(Error::custom(format!(
+                "roaming answer {}: {}",
+                self.result.result_code,
+                self.result.description.as_deref().unwrap_or("")
+            )));
+        }
+        let (payload, meta) = match (&self.phy_payload, &self.dl_meta_data) {
+            (Some(payload), Some(meta)) => (hex::decode(payload.trim_start_matches("0x"))?, meta),
+            _ => return Ok(None),
+        };
+        let rx_delay_1 = match uplink
+            .routing()
+            .as_ref()
+            .and_then(|routing| routing.data.as_ref())
+        {
+            Some(RoutingData::Eui(_)) => JOIN_ACCEPT_DELAY1,
+            _ => meta.rx_delay_1.unwrap_or(RECEIVE_DELAY1),
+        };
+        let rx1_timestamp = uplink.timestamp + rx_delay_1 * 1_000_000;
+        let rx2_window = match (meta.dl_freq_2, meta.data_rate_2) {
+            (Some(frequency), Some(data_rate)) => Some(WindowV1 {
+                timestamp: rx1_timestamp + 1_000_000,
+                frequency,
+                datarate: datarate_name(region, data_rate)?.to_string(),
+            }),
+            _ => None,
+        };
+        let (frequency, datarate) = match (meta.dl_freq_1, meta.data_rate_1) {
+            (Some(frequency), Some(data_rate)) => {
+                (frequency, datarate_name(region, data_rate)?.to_string())
+            }
+            // Only an rx2 window is given, use it for rx1 as well
+            _ => match &rx2_window {
+                Some(window) => (window.frequency, window.datarate.clone()),
+                None => return Ok(None),
+            },
+        };
+        let packet = helium_proto::Packet {
+            r#type: PacketType::Lorawan.into(),
+            signal_strength: 0.0,
+            snr: 0.0,
+            frequency,
+            timestamp: rx1_timestamp,
+            datarate,
+            routing: None,
+            payload,
+            rx2_window,
+            oui: 0,
+        };
+        Ok(Some(Packet::from(packet)))
+    }
+}
+
+/// Returns the backend interfaces RFRegion name for a region.
+pub fn rf_region(region: &Region) -> String {
+    let name = region.to_string();
+    match name.as_str() {
+        "US915" => "US902".to_string(),
+        "AU915" => "Australia915".to_string(),
+        "CN470" => "China470".to_string(),
+        "CN779" => "China779".to_string(),
+        "KR920" => "SouthKorea920".to_string(),
+        "IN865" => "India865".to_string(),
+        other if other.starts_with("AS923") => "AS923".to_string(),
+        _ => name,
+    }
+}
+
+const US_DATARATES: &[(u8, &str)] = &[
+    (0, "SF10BW125"),
+    (1, "SF9BW125"),
+    (2, "SF8BW125"),
+    (3, "SF7BW125"),
+    (4, "SF8BW500"),
+    (8, "SF12BW500"),
+    (9, "SF11BW500"),
+    (10, "SF10BW500"),
+    (11, "SF9BW500"),
+    (12, "SF8BW500"),
+    (13, "SF7BW500"),
+];
+
+const AU_DATARATES: &[(u8, &str)] = &[
+    (0, "SF12BW125"),
+    (1, "SF11BW125"),
+    (2, "SF10BW125"),
+    (3, "SF9BW125"),
+    (4, "SF8BW125"),
+    (5, "SF7BW125"),
+    (6, "SF8BW500"),
+    (8, "SF12BW500"),
+    (9, "SF11BW500"),
+    (10, "SF10BW500"),
+    (11, "SF9BW500"),
+    (12, "SF8BW500"),
+    (13, "SF7BW500"),
+];
+
+const EU_DATARATES: &[(u8, &str)] = &[
+    (0, "SF12BW125"),
+    (1, "SF11BW125"),
+    (2, "SF10BW125"),
+    (3, "SF9BW125"),
+    (4, "SF8BW125"),
+    (5, "SF7BW125"),
+    (6, "SF7BW250"),
+];
+
+fn datarates(region: &Region) -> &'static [(u8, &'static str)] {
+    match region.to_string().as_str() {
+        "US915" => US_DATARATES,
+        "AU915" => AU_DATARATES,
+        _ => EU_DATARATES,
+    }
+}
+
+/// Returns the regional data rate index for a semtech datarate name.
+pub fn datarate_index(region: &Region, name: &str) -> Option<u8> {
+    datarates(region)
+        .iter()
+        .find(|(_, datarate)| *datarate == name)
+        .map(|(index, _)| *index)
+}
+
+/// Returns the semtech datarate name for a regional data rate index.
+pub fn datarate_name(region: &Region, index: u8) -> Result<&'static str> {
+    datarates(region)
+        .iter()
+        .find(|(datarate_index, _)| *datarate_index == index)
+        .map(|(_, name)| *name)
+        .ok_or_else(|| Error::custom(format!("unknown data rate {index} for {region}")))
+}
+
+/// Formats a system time as an ISO 8601 UTC timestamp as required for the
+/// RecvTime field.
+pub fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use helium_proto::{Eui, RoutingInformation};
+    use std::time::UNIX_EPOCH;
+
+    fn uplink(routing: RoutingData) -> Packet {
+        Packet::from(helium_proto::Packet {
+            r#type: PacketType::Lorawan.into(),
+            frequency: 904.1,
+            timestamp: 10_000_000,
+            datarate: "SF10BW125".to_string(),
+            routing: Some(RoutingInformation {
+                data: Some(routing),
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn us915() -> Region {
+        Region::from_i32(helium_proto::Region::Us915.into()).expect("region")
+    }
+
+    fn dl_answer(meta: &str) -> Answer {
+        let json = format!(
+            r#"{{"Result": {{"ResultCode": "Success"}}, "PHYPayload": "0102", "DLMetaData": {meta}}}"#
+        );
+        serde_json::from_str(&json).expect("answer")
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!("1970-01-01T00:00:00.000Z", format_time(UNIX_EPOCH));
+        assert_eq!(
+            "2001-09-09T01:46:40.250Z",
+            format_time(UNIX_EPOCH + Duration::from_millis(1_000_000_000_250))
+        );
+    }
+
+    #[test]
+    fn invalid_payload() {
+        let uplink = uplink(RoutingData::Devaddr(0x2600_0001));
+        let meta = r#"{"DLFreq1": 923.3, "DataRate1": 10}"#;
+        for payload in ["010", "0xé", "zz"] {
+            let json = format!(
+                r#"{{"Result": {{"ResultCode": "Success"}}, "PHYPayload": "{payload}", "DLMetaData": {meta}}}"#
+            );
+            let answer: Answer = serde_json::from_str(&json).expect("answer");
+            assert!(answer.to_downlink(&uplink, &us915()).is_err());
+        }
+        let json = format!(
+            r#"{{"Result": {{"ResultCode": "Success"}}, "PHYPayload": "0x0102", "DLMetaData": {meta}}}"#
+        );
+        let answer: Answer = serde_json::from_str(&json).expect("answer");
+        let downlink = answer
+            .to_downlink(&uplink, &us915())
+            .expect("downlink")
+            .expect("some downlink");
+        assert_eq!(&[1, 2], downlink.payload());
+    }
+
+    #[test]
+    fn session_lifetime() {
+        let answer: Answer =
+            serde_json::from_str(r#"{"Result": {"ResultCode": "Success"}, "Lifetime": 60}"#)
+                .expect("answer");
+        assert!(answer.is_success());
+        assert_eq!(Duration::from_secs(60), answer.lifetime());
+        let answer: Answer =
+            serde_json::from_str(r#"{"Result": {"ResultCode": "Success"}}"#).expect("answer");
+        assert_eq!(Duration::ZERO, answer.lifetime());
+    }
+
+    #[test]
+    fn data_downlink() {
+        let uplink = uplink(RoutingData::Devaddr(0x2600_0001));
+        let answer = dl_answer(
+            r#"{"DLFreq1": 923.3, "DataRate1": 10, "RXDelay1": 2, "DLFreq2": 923.3, "DataRate2": 8}"#,
+        );
+        let downlink = answer
+            .to_downlink(&uplink, &us915())
+            .expect("downlink")
+            .expect("some downlink");
+        assert_eq!(12_000_000, downlink.timestamp);
+        assert_eq!(923.3, downlink.frequency);
+        assert_eq!("SF10BW500", downlink.datarate);
+        assert_eq!(&[1, 2], downlink.payload());
+        let rx2 = downlink.to_packet().rx2_window.expect("rx2 window");
+        assert_eq!(13_000_000, rx2.timestamp);
+        assert_eq!("SF12BW500", rx2.datarate);
+
+        // RXDelay1 defaults to one second
+        let answer = dl_answer(r#"{"DLFreq1": 923.3, "DataRate1": 10}"#);
+        let downlink = answer
+            .to_downlink(&uplink, &us915())
+            .expect("downlink")
+            .expect("some downlink");
+        assert_eq!(11_000_000, downlink.timestamp);
+        assert!(downlink.to_packet().rx2_window.is_none());
+    }
+
+    #[test]
+    fn join_accept_downlink() {
+        let uplink = uplink(RoutingData::Eui(Eui {
+            deveui: 1,
+            appeui: 2,
+        }));
+        let answer = dl_answer(r#"{"DLFreq1": 923.3, "DataRate1": 10, "RXDelay1": 1}"#);
+        let downlink = answer
+            .to_downlink(&uplink, &us915())
+            .expect("downlink")
+            .expect("some downlink");
+        assert_eq!(15_000_000, downlink.timestamp);
+
+        // Only an rx2 window is given, which is used for rx1 as well
+        let answer = dl_answer(r#"{"DLFreq2": 923.3, "DataRate2": 8}"#);
+        let downlink = answer
+            .to_downlink(&uplink, &us915())
+            .expect("downlink")
+            .expect("some downlink");
+        assert_eq!(15_000_000, downlink.timestamp);
+        assert_eq!("SF12BW500", downlink.datarate);
+        let rx2 = downlink.to_packet().rx2_window.expect("rx2 window");
+        assert_eq!(16_000_000, rx2.timestamp);
+    }
+
+    #[test]
+    fn failed_answer() {
+        let uplink = uplink(RoutingData::Devaddr(0x2600_0001));
+        let answer: Answer = serde_json::from_str(
+            r#"{"Result": {"ResultCode": "UnknownDevAddr", "Description": "no device"}}"#,
+        )
+        .expect("answer");
+        assert!(answer.to_downlink(&uplink, &us915()).is_err());
+    }
+}
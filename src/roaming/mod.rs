@@ -0,0 +1,461 @@
+//! This module provides passive roaming for packets of devices that belong to
+//! other LoRaWAN networks.
+//!
+//! Uplinks with a foreign devaddr or with a join EUI of a roaming partner are
+//! forwarded as LoRaWAN Backend Interfaces messages to the partner's HTTP
+//! endpoint. Joins and data uplinks of devices without a roaming session are
+//! sent as PRStartReq. A PRStartAns with a non-zero lifetime starts a session
+//! for the devaddr, during which data uplinks are sent as XmitDataReq until
+//! the session expires or an XmitDataReq fails. Downlinks included in the
+//! partner's answer are transmitted in the uplink's receive windows.
+
+pub mod message;
+
+use crate::{
+    curl, gateway,
+    settings::{ProxySettings, RoamingPartnerSettings, RoamingSettings},
+    sync, Error, Keypair, Packet, Region, Result, Settings,
+};
+use futures::TryFutureExt;
+use helium_proto::{routing_information::Data as RoutingData, RoutingInformation};
+use lorawan::subnet;
+use message::{Answer, GWInfo, ULMetaData, UplinkRequest, PR_START_REQ, XMIT_DATA_REQ};
+use slog::{debug, info, o, warn, Logger};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Roaming requests for uplinks that have been held for longer than this are
+/// dropped since a downlink could not be delivered anymore.
+const MAX_HOLD_TIME: Duration = Duration::from_millis(800);
+
+#[derive(Debug)]
+pub enum Message {
+    Uplink {
+        packet: Packet,
+        partner: Arc<RoamingPartnerSettings>,
+        received: Instant,
+    },
+    RegionChanged(Region),
+    Session {
+        devaddr: u32,
+        lifetime: Duration,
+    },
+}
+
+/// The set of roaming partners to look up the partner for a packet in.
+#[derive(Debug, Default)]
+pub struct Partners {
+    local_netids: Vec<u32>,
+    partners: Vec<Arc<RoamingPartnerSettings>>,
+}
+
+impl Partners {
+    pub fn new(settings: &RoamingSettings) -> Self {
+        Self {
+            local_netids: settings.local_netids.clone(),
+            partners: settings.partners.iter().cloned().map(Arc::new).collect(),
+        }
+    }
+
+    /// Returns the roaming partner for the given routing information. Helium
+    /// devaddrs never match a partner.
+    pub fn find(
+        &self,
+        routing: &Option<RoutingInformation>,
+    ) -> Option<&Arc<RoamingPartnerSettings>> {
+        if self.partners.is_empty() {
+            return None;
+        }
+        match routing.as_ref().and_then(|routing| routing.data.as_ref()) {
+            Some(RoutingData::Devaddr(devaddr)) => {
+                if subnet::is_local_devaddr(*devaddr, &self.local_netids) {
+                    return None;
+                }
+                let netid = subnet::netid_from_devaddr(*devaddr);
+                self.partners.iter().find(|partner| partner.netid == netid)
+            }
+            Some(RoutingData::Eui(eui)) => self.partners.iter().find(|partner| {
+                partner
+                    .join_euis
+                    .iter()
+                    .any(|range| range.start <= eui.appeui && eui.appeui <= range.end)
+            }),
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageSender {
+    sender: sync::MessageSender<Message>,
+    partners: Arc<Partners>,
+}
+pub type MessageReceiver = sync::MessageReceiver<Message>;
+
+pub fn message_channel(settings: &Settings, size: usize) -> (MessageSender, MessageReceiver) {
+    let (sender, receiver) = sync::message_channel(size);
+    let partners = settings
+        .roaming
+        .as_ref()
+        .map(Partners::new)
+        .unwrap_or_default();
+    let sender = MessageSender {
+        sender,
+        partners: Arc::new(partners),
+    };
+    (sender, receiver)
+}
+
+impl MessageSender {
+    /// Forwards the given uplink to a roaming partner if one matches the
+    /// packet. Returns whether the packet was forwarded.
+    pub async fn uplink(&self, packet: &Packet, received: Instant) -> bool {
+        let partner = match self.partners.find(packet.routing()) {
+            Some(partner) => partner.clone(),
+            None => return false,
+        };
+        self.sender
+            .0
+            .send(Message::Uplink {
+                packet: packet.clone(),
+                partner,
+                received,
+            })
+            .await
+            .is_ok()
+    }
+
+    pub async fn region_changed(&self, region: Region) {
+        let _ = self.sender.0.send(Message::RegionChanged(region)).await;
+    }
+
+    /// Starts a roaming session for the given devaddr, or ends it for a zero
+    /// lifetime.
+    pub async fn session(&self, devaddr: u32, lifetime: Duration) {
+        let _ = self
+            .sender
+            .0
+            .send(Message::Session { devaddr, lifetime })
+            .await;
+    }
+}
+
+pub struct Roamer {
+    keypair: Arc<Keypair>,
+    sender_id: String,
+    region: Region,
+    downlinks: gateway::MessageSender,
+    messages: MessageReceiver,
+    sender: MessageSender,
+    /// Expiry of the roaming sessions by devaddr
+    sessions: HashMap<u32, Instant>,
+    transaction_id: u32,
+    enabled: bool,
+    proxy: Option<ProxySettings>,
+}
+
+impl Roamer {
+    pub fn new(
+        settings: &Settings,
+        downlinks: gateway::MessageSender,
+        messages: MessageReceiver,
+        sender: MessageSender,
+    ) -> Self {
+        let (enabled, sender_id) = match &settings.roaming {
+            Some(roaming) => (!roaming.partners.is_empty(), roaming.sender_id),
+            None => (false, 0),
+        };
+        Self {
+            keypair: settings.keypair.clone(),
+            sender_id: format!("{sender_id:06X}"),
+            region: settings.region,
+            downlinks,
+            messages,
+            sender,
+            sessions: HashMap::new(),
+            transaction_id: 0,
+            enabled,
+            proxy: settings.proxy.clone(),
+        }
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "roaming"));
+        if !self.enabled {
+            info!(logger, "disabling");
+            return Ok(());
+        }
+        info!(logger, "starting"; "sender_id" => &self.sender_id);
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                message = self.messages.recv() => match message {
+                    Some(Message::Uplink { packet, partner, received }) =>
+                        self.handle_uplink(&logger, packet, partner, received),
+                    Some(Message::RegionChanged(region)) => {
+                        self.region = region;
+                        info!(logger, "updated region"; "region" => region);
+                    }
+                    Some(Message::Session { devaddr, lifetime }) =>
+                        self.handle_session(&logger, devaddr, lifetime),
+                    None => {
+                        warn!(logger, "ignoring closed messages channel");
+                        return Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_uplink(
+        &mut self,
+        logger: &Logger,
+        packet: Packet,
+        partner: Arc<RoamingPartnerSettings>,
+        received: Instant,
+    ) {
+        if received.elapsed() > MAX_HOLD_TIME {
+            warn!(logger, "dropping stale roaming uplink");
+            return;
+        }
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let request = match self.mk_request(&packet, &partner) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!(logger, "ignoring roaming uplink: {err:?}");
+                return;
+            }
+        };
+        let logger = logger.new(o!(
+            "netid" => format!("{:06X}", partner.netid),
+            "transaction_id" => self.transaction_id,
+        ));
+        let devaddr = match packet
+            .routing()
+            .as_ref()
+            .and_then(|routing| routing.data.as_ref())
+        {
+            Some(RoutingData::Devaddr(devaddr)) => Some(*devaddr),
+            _ => None,
+        };
+        let downlinks = self.downlinks.clone();
+        let sender = self.sender.clone();
+        let region = self.region;
+        let proxy = self.proxy.clone();
+        // Requests are sent concurrently so a slow partner does not hold up
+        // uplinks for other partners
+        tokio::spawn(async move {
+            debug!(logger, "sending {}", request.message_type);
+            let answer = match serde_json::to_string(&request) {
+                Ok(body) => {
+                    curl::post(partner.uri.to_string(), body, proxy.as_ref(), |output| {
+                        serde_json::from_slice::<Answer>(output).map_err(Error::from)
+                    })
+                    .await
+                }
+                Err(err) => Err(Error::from(err)),
+            };
+            if let Some(devaddr) = devaddr {
+                if let Some(lifetime) = session_update(request.message_type, &answer) {
+                    debug!(logger, "roaming session update";
+                        "devaddr" => format!("{devaddr:08X}"),
+                        "lifetime" => lifetime.as_secs());
+                    sender.session(devaddr, lifetime).await;
+                }
+            }
+            match answer.and_then(|answer| answer.to_downlink(&packet, &region)) {
+                Ok(Some(downlink)) => {
+                    info!(logger, "roaming downlink");
+                    let _ = downlinks
+                        .downlink(downlink)
+                        .inspect_err(|_| warn!(logger, "failed to push roaming downlink"))
+                        .await;
+                }
+                Ok(None) => (),
+                Err(err) => warn!(logger, "roaming request failed: {err:?}"),
+            }
+        });
+    }
+
+    fn handle_session(&mut self, logger: &Logger, devaddr: u32, lifetime: Duration) {
+        let now = Instant::now();
+        self.sessions.retain(|_, expiry| *expiry > now);
+        if lifetime.is_zero() {
+            if self.sessions.remove(&devaddr).is_some() {
+                info!(logger, "ended roaming session"; "devaddr" => format!("{devaddr:08X}"));
+            }
+        } else {
+            self.sessions.insert(devaddr, now + lifetime);
+        }
+    }
+
+    fn has_session(&self, devaddr: u32) -> bool {
+        matches!(self.sessions.get(&devaddr), Some(expiry) if *expiry > Instant::now())
+    }
+
+    fn mk_request(
+        &self,
+        packet: &Packet,
+        partner: &RoamingPartnerSettings,
+    ) -> Result<UplinkRequest> {
+        let (message_type, dev_addr, dev_eui) = match packet
+            .routing()
+            .as_ref()
+            .and_then(|routing| routing.data.as_ref())
+        {
+            Some(RoutingData::Devaddr(devaddr)) => {
+                let message_type = if self.has_session(*devaddr) {
+                    XMIT_DATA_REQ
+                } else {
+                    PR_START_REQ
+                };
+                (message_type, Some(format!("{devaddr:08X}")), None)
+            }
+            Some(RoutingData::Eui(eui)) => {
+                (PR_START_REQ, None, Some(format!("{:016X}", eui.deveui)))
+            }
+            None => return Err(Error::custom("no routing information")),
+        };
+        let rf_region = message::rf_region(&self.region);
+        Ok(UplinkRequest {
+            protocol_version: message::PROTOCOL_VERSION,
+            sender_id: self.sender_id.clone(),
+            receiver_id: format!("{:06X}", partner.netid),
+            transaction_id: self.transaction_id,
+            message_type,
+            phy_payload: hex::encode_upper(packet.payload()),
+            ul_meta_data: ULMetaData {
+                dev_addr,
+                dev_eui,
+                data_rate: message::datarate_index(&self.region, &packet.datarate),
+                ul_freq: packet.frequency,
+                recv_time: message::format_time(SystemTime::now()),
+                rf_region: rf_region.clone(),
+                gw_cnt: 1,
+                gw_info: vec![GWInfo {
+                    id: hex::encode_upper(self.keypair.public_key().to_vec()),
+                    rf_region,
+                    rssi: packet.signal_strength as i32,
+                    snr: packet.snr,
+                    dl_allowed: true,
+                }],
+            },
+        })
+    }
+}
+
+/// Returns the session change for a devaddr after the answer to a request: a
+/// successful PRStartAns starts a session for its lifetime and a failed
+/// XmitDataReq ends the session so the next uplink starts a new one.
+fn session_update(message_type: &str, answer: &Result<Answer>) -> Option<Duration> {
+    match answer {
+        Ok(answer) if answer.is_success() => {
+            (message_type == PR_START_REQ).then(|| answer.lifetime())
+        }
+        _ => (message_type == XMIT_DATA_REQ).then_some(Duration::ZERO),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::EuiRangeSettings;
+    use helium_proto::Eui;
+
+    fn partners() -> Partners {
+        let partner = |netid, join_euis| RoamingPartnerSettings {
+            netid,
+            uri: format!("http://partner{netid}.example.com/roaming")
+                .parse()
+                .expect("uri"),
+            join_euis,
+        };
+        Partners::new(&RoamingSettings {
+            sender_id: 0x000024,
+            local_netids: vec![0x000024],
+            partners: vec![
+                partner(
+                    0x000013,
+                    vec![EuiRangeSettings {
+                        start: 0x0000_0000_0000_1000,
+                        end: 0x0000_0000_0000_1FFF,
+                    }],
+                ),
+                partner(0x000014, vec![]),
+            ],
+        })
+    }
+
+    fn find_netid(partners: &Partners, data: RoutingData) -> Option<u32> {
+        partners
+            .find(&Some(RoutingInformation { data: Some(data) }))
+            .map(|partner| partner.netid)
+    }
+
+    #[test]
+    fn find_devaddr() {
+        let partners = partners();
+        // NetID 0x000013 and 0x000014 devaddrs
+        assert_eq!(
+            Some(0x000013),
+            find_netid(&partners, RoutingData::Devaddr(0x2600_0001))
+        );
+        assert_eq!(
+            Some(0x000014),
+            find_netid(&partners, RoutingData::Devaddr(0x2800_0001))
+        );
+        // A local NetID 0x000024 devaddr is never roamed
+        assert_eq!(
+            None,
+            find_netid(&partners, RoutingData::Devaddr(0x4800_0001))
+        );
+        // No partner for NetID 0x000015
+        assert_eq!(
+            None,
+            find_netid(&partners, RoutingData::Devaddr(0x2A00_0001))
+        );
+    }
+
+    #[test]
+    fn find_join_eui() {
+        let partners = partners();
+        let eui = |appeui| RoutingData::Eui(Eui { deveui: 1, appeui });
+        assert_eq!(Some(0x000013), find_netid(&partners, eui(0x1000)));
+        assert_eq!(Some(0x000013), find_netid(&partners, eui(0x1FFF)));
+        assert_eq!(None, find_netid(&partners, eui(0x2000)));
+        assert!(partners.find(&None).is_none());
+    }
+
+    #[test]
+    fn session_updates() {
+        let answer =
+            |json: &str| -> Result<Answer> { serde_json::from_str(json).map_err(Error::from) };
+        let started = answer(r#"{"Result": {"ResultCode": "Success"}, "Lifetime": 60}"#);
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            session_update(PR_START_REQ, &started)
+        );
+        // Stateless partners do not grant a session
+        let stateless = answer(r#"{"Result": {"ResultCode": "Success"}}"#);
+        assert_eq!(
+            Some(Duration::ZERO),
+            session_update(PR_START_REQ, &stateless)
+        );
+        assert_eq!(None, session_update(XMIT_DATA_REQ, &stateless));
+        // A failed XmitDataReq ends the session, a failed PRStartReq has none
+        let failed = answer(r#"{"Result": {"ResultCode": "UnknownDevAddr"}}"#);
+        assert_eq!(Some(Duration::ZERO), session_update(XMIT_DATA_REQ, &failed));
+        assert_eq!(None, session_update(PR_START_REQ, &failed));
+        let transport = Err(Error::custom("curl post failed"));
+        assert_eq!(
+            Some(Duration::ZERO),
+            session_update(XMIT_DATA_REQ, &transport)
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{
+    settings::{AccountingSettings, OuiLimitSettings},
+    KeyedUri, Result,
+};
+use serde::{Deserialize, Serialize};
+use slog::{info, warn, Logger};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Traffic counters for a router client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counters {
+    /// Uplinks delivered to the router
+    pub uplinks: u64,
+    /// Payload bytes of the delivered uplinks
+    pub bytes: u64,
+    /// Data credits spent on the delivered uplinks
+    pub dc: u64,
+    /// Downlinks received from the router
+    pub downlinks: u64,
+    /// Failed uplink deliveries
+    pub failures: u64,
+}
+
+impl Counters {
+    pub fn add(&mut self, other: &Counters) {
+        self.uplinks += other.uplinks;
+        self.bytes += other.bytes;
+        self.dc += other.dc;
+        self.downlinks += other.downlinks;
+        self.failures += other.failures;
+    }
+}
+
+/// Counters for a router or, without a uri, the totals for an OUI
+#[derive(Debug, Clone)]
+pub struct AccountingEntry {
+    pub oui: u32,
+    pub uri: Option<String>,
+    pub counters: Counters,
+    /// Uplinks not sent to the OUI because of a limit
+    pub limited: u64,
+}
+
+/// The persisted form of the counters
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    routers: Vec<RouterState>,
+    limited: HashMap<u32, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RouterState {
+    oui: u32,
+    uri: String,
+    #[serde(flatten)]
+    counters: Counters,
+}
+
+#[derive(Debug)]
+struct RateWindow {
+    start: Instant,
+    count: u32,
+}
+
+/// Keeps the last known traffic counters of all router clients, including
+/// the ones that have since been removed, and enforces per-OUI limits.
+#[derive(Debug)]
+pub struct Accounting {
+    path: Option<PathBuf>,
+    save_interval: Duration,
+    saved: Instant,
+    limits: HashMap<u32, OuiLimitSettings>,
+    routers: HashMap<(u32, String), Counters>,
+    limited: HashMap<u32, u64>,
+    windows: HashMap<u32, RateWindow>,
+}
+
+impl Accounting {
+    pub fn new(settings: &AccountingSettings) -> Self {
+        Self {
+            path: settings.path.clone(),
+            save_interval: Duration::from_secs(settings.save_interval),
+            saved: Instant::now(),
+            limits: settings
+                .limits
+                .iter()
+                .map(|limit| (limit.oui, limit.clone()))
+                .collect(),
+            routers: HashMap::new(),
+            limited: HashMap::new(),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Loads previously persisted counters, if any
+    pub fn load(&mut self, logger: &Logger) {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return,
+        };
+        let state: Result<State> = fs::read(path)
+            .map_err(Into::into)
+            .and_then(|data| serde_json::from_slice(&data).map_err(Into::into));
+        match state {
+            Ok(state) => {
+                self.routers = state
+                    .routers
+                    .into_iter()
+                    .map(|router| ((router.oui, router.uri), router.counters))
+                    .collect();
+                self.limited = state.limited;
+                info!(logger, "loaded accounting";
+                    "path" => path.to_string_lossy().to_string(),
+                    "routers" => self.routers.len());
+            }
+            Err(err) => warn!(logger, "ignoring invalid accounting file: {err:?}"),
+        }
+    }
+
+    /// The last known counters for the router with the given OUI and uri
+    pub fn counters(&self, oui: u32, uri: &KeyedUri) -> Counters {
+        self.routers
+            .get(&(oui, uri.uri.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn update(&mut self, oui: u32, uri: &KeyedUri, counters: Counters) {
+        self.routers.insert((oui, uri.uri.to_string()), counters);
+    }
+
+    /// The data credits spent on an OUI by all of its routers, including the
+    /// removed ones. The given counters of the current routers take the
+    /// place of their last known counters.
+    pub fn dc_spent<'a, I>(&self, oui: u32, current: I) -> u64
+    where
+        I: IntoIterator<Item = (&'a KeyedUri, Counters)>,
+    {
+        let mut spent: HashMap<String, u64> = self
+            .routers
+            .iter()
+            .filter(|((router_oui, _), _)| *router_oui == oui)
+            .map(|((_, uri), counters)| (uri.clone(), counters.dc))
+            .collect();
+        for (uri, counters) in current {
+            spent.insert(uri.uri.to_string(), counters.dc);
+        }
+        spent.values().sum()
+    }
+
+    /// Checks whether an uplink may be sent to the given OUI, counting it
+    /// against the OUI's rate limit. The counters of the OUI's current
+    /// routers are only collected when the OUI has a budget.
+    pub fn allow<'a, F, I>(&mut self, oui: u32, current: F) -> bool
+    where
+        F: FnOnce() -> I,
+        I: IntoIterator<Item = (&'a KeyedUri, Counters)>,
+    {
+        let limit = match self.limits.get(&oui) {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let over_budget = limit
+            .dc_budget
+            .map_or(false, |budget| self.dc_spent(oui, current()) >= budget);
+        let over_rate = !over_budget
+            && limit.uplinks_per_minute.map_or(false, |max| {
+                let window = self.windows.entry(oui).or_insert(RateWindow {
+                    start: Instant::now(),
+                    count: 0,
+                });
+                if window.start.elapsed() >= RATE_WINDOW {
+                    window.start = Instant::now();
+                    window.count = 0;
+                }
+                window.count += 1;
+                window.count > max
+            });
+        if over_budget || over_rate {
+            *self.limited.entry(oui).or_default() += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Returns the counters of all known routers, followed by the totals per
+    /// OUI, sorted by OUI.
+    pub fn entries(&self) -> Vec<AccountingEntry> {
+        let mut totals: HashMap<u32, Counters> = self
+            .limited
+            .keys()
+            .map(|oui| (*oui, Counters::default()))
+            .collect();
+        let mut entries: Vec<AccountingEntry> = self
+            .routers
+            .iter()
+            .map(|((oui, uri), counters)| {
+                totals.entry(*oui).or_default().add(counters);
+                AccountingEntry {
+                    oui: *oui,
+                    uri: Some(uri.clone()),
+                    counters: *counters,
+                    limited: 0,
+                }
+            })
+            .collect();
+        entries.extend(totals.into_iter().map(|(oui, counters)| AccountingEntry {
+            oui,
+            uri: None,
+            counters,
+            limited: self.limited.get(&oui).copied().unwrap_or(0),
+        }));
+        entries.sort_unstable_by(|a, b| {
+            (a.oui, a.uri.is_none(), &a.uri).cmp(&(b.oui, b.uri.is_none(), &b.uri))
+        });
+        entries
+    }
+
+    pub fn should_save(&self) -> bool {
+        self.path.is_some() && self.saved.elapsed() >= self.save_interval
+    }
+
+    pub fn save(&mut self, logger: &Logger) {
+        self.saved = Instant::now();
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let state = State {
+            routers: self
+                .routers
+                .iter()
+                .map(|((oui, uri), counters)| RouterState {
+                    oui: *oui,
+                    uri: uri.clone(),
+                    counters: *counters,
+                })
+                .collect(),
+            limited: self.limited.clone(),
+        };
+        let result: Result = serde_json::to_vec(&state)
+            .map_err(Into::into)
+            .and_then(|data| fs::write(path, data).map_err(Into::into));
+        if let Err(err) = result {
+            warn!(logger, "failed to save accounting: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PublicKey;
+    use std::{str::FromStr, sync::Arc};
+
+    fn router(host: u8) -> KeyedUri {
+        KeyedUri {
+            uri: format!("http://10.0.0.{host}:8080").parse().expect("uri"),
+            pubkey: Arc::new(
+                PublicKey::from_str("11tk4zzbyfMPYYHYda255ACoqfYFVdrUSoCWrCYfn8BoyuYrERK")
+                    .expect("public key"),
+            ),
+        }
+    }
+
+    fn dc(dc: u64) -> Counters {
+        Counters {
+            dc,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let mut accounting = Accounting::new(&AccountingSettings {
+            path: None,
+            save_interval: 300,
+            limits: vec![
+                OuiLimitSettings {
+                    oui: 1,
+                    uplinks_per_minute: Some(2),
+                    dc_budget: None,
+                },
+                OuiLimitSettings {
+                    oui: 2,
+                    uplinks_per_minute: None,
+                    dc_budget: Some(10),
+                },
+            ],
+        });
+        let (router1, router2) = (router(1), router(2));
+        assert!(accounting.allow(1, Vec::new));
+        assert!(accounting.allow(1, Vec::new));
+        assert!(!accounting.allow(1, Vec::new));
+        assert!(accounting.allow(2, || vec![(&router1, dc(9))]));
+        assert!(!accounting.allow(2, || vec![(&router1, dc(4)), (&router2, dc(6))]));
+        assert!(accounting.allow(3, || vec![(&router1, dc(100))]));
+        assert_eq!(Some(&1), accounting.limited.get(&1));
+        assert_eq!(Some(&1), accounting.limited.get(&2));
+    }
+
+    #[test]
+    fn removed_router_budget() {
+        let mut accounting = Accounting::new(&AccountingSettings {
+            path: None,
+            save_interval: 300,
+            limits: vec![OuiLimitSettings {
+                oui: 1,
+                uplinks_per_minute: None,
+                dc_budget: Some(10),
+            }],
+        });
+        let (router1, router2) = (router(1), router(2));
+        accounting.update(1, &router1, dc(4));
+        accounting.update(1, &router2, dc(2));
+        // Current counters replace the last known ones of a router
+        assert_eq!(9, accounting.dc_spent(1, vec![(&router2, dc(5))]));
+        // The spend of router 1 still counts after it was removed
+        assert!(accounting.allow(1, || vec![(&router2, dc(5))]));
+        assert!(!accounting.allow(1, || vec![(&router2, dc(6))]));
+        assert_eq!(0, accounting.dc_spent(2, vec![]));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::{gateway, mirror, settings::DownlinkPolicy, sync, Error, Packet, Result, Settings};
+use futures::TryFutureExt;
+use slog::{info, o, warn, Logger};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+/// Arbitrations are kept around for this long after their uplink to discard
+/// late downlinks for it.
+const ARBITRATION_TTL: Duration = Duration::from_secs(10);
+
+/// The time a downlink is held back waiting for the downlinks of higher
+/// priority OUIs before it is transmitted anyway
+const DOWNLINK_HOLD_TIME: Duration = Duration::from_millis(400);
+
+#[derive(Debug)]
+pub enum Message {
+    Uplink {
+        uplink: Vec<u8>,
+        responders: Vec<u32>,
+    },
+    Response {
+        oui: u32,
+        uplink: Vec<u8>,
+        downlink: Option<Packet>,
+    },
+    Timeout {
+        uplink: Vec<u8>,
+    },
+}
+
+pub type MessageSender = sync::MessageSender<Message>;
+pub type MessageReceiver = sync::MessageReceiver<Message>;
+
+pub fn message_channel(size: usize) -> (MessageSender, MessageReceiver) {
+    sync::message_channel(size)
+}
+
+impl MessageSender {
+    /// Registers an uplink with the given packet hash that was sent to routers
+    /// of the given OUIs, with one entry per router
+    pub async fn uplink(&self, uplink: Vec<u8>, responders: Vec<u32>) {
+        let _ = self.0.send(Message::Uplink { uplink, responders }).await;
+    }
+
+    /// Reports the response of a router of the given OUI to the uplink with
+    /// the given packet hash
+    pub async fn response(&self, oui: u32, uplink: Vec<u8>, downlink: Option<Packet>) -> Result {
+        self.0
+            .send(Message::Response {
+                oui,
+                uplink,
+                downlink,
+            })
+            .map_err(|_| Error::channel())
+            .await
+    }
+}
+
+/// Runs downlink arbitration in its own task, so arbitrated downlinks are
+/// never held up by the dispatcher.
+pub struct Arbiter {
+    arbiter: DownlinkArbiter,
+    messages: MessageReceiver,
+    sender: MessageSender,
+    downlinks: gateway::MessageSender,
+    mirror: mirror::MessageSender,
+}
+
+impl Arbiter {
+    pub fn new(
+        settings: &Settings,
+        messages: MessageReceiver,
+        sender: MessageSender,
+        downlinks: gateway::MessageSender,
+        mirror: mirror::MessageSender,
+    ) -> Self {
+        Self {
+            arbiter: DownlinkArbiter::new(settings.downlink_policy.clone()),
+            messages,
+            sender,
+            downlinks,
+            mirror,
+        }
+    }
+
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+        let logger = logger.new(o!("module" => "arbiter"));
+        info!(logger, "starting");
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                message = self.messages.recv() => match message {
+                    Some(Message::Uplink { uplink, responders }) => self.handle_uplink(uplink, responders),
+                    Some(Message::Response { oui, uplink, downlink }) => {
+                        let outcomes = self.arbiter.response(&uplink, oui, downlink);
+                        self.handle_outcomes(outcomes, &logger).await
+                    },
+                    Some(Message::Timeout { uplink }) => {
+                        let outcomes = self.arbiter.timeout(&uplink).into_iter().collect();
+                        self.handle_outcomes(outcomes, &logger).await
+                    },
+                    None => {
+                        warn!(logger, "ignoring closed messages channel");
+                        return Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Registers an uplink for arbitration, and schedules the transmission of
+    /// a held back downlink if needed.
+    fn handle_uplink(&mut self, uplink: Vec<u8>, responders: Vec<u32>) {
+        if !self.arbiter.uplink(uplink.clone(), responders) {
+            return;
+        }
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            time::sleep(DOWNLINK_HOLD_TIME).await;
+            let _ = sender.0.send(Message::Timeout { uplink }).await;
+        });
+    }
+
+    async fn handle_outcomes(&mut self, outcomes: Vec<Outcome>, logger: &Logger) {
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Forward(packet) => {
+                    let _ = self
+                        .downlinks
+                        .downlink(packet)
+                        .inspect_err(|_| warn!(logger, "failed to push downlink"))
+                        .await;
+                }
+                Outcome::Discard {
+                    oui,
+                    packet,
+                    selected,
+                } => {
+                    info!(logger, "discarded downlink";
+                        "oui" => oui,
+                        "selected_oui" => selected);
+                    self.mirror.downlink(
+                        &packet,
+                        mirror::DownlinkOutcome::Discarded {
+                            oui,
+                            selected_oui: selected,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The result of arbitrating a router response
+#[derive(Debug)]
+pub enum Outcome {
+    /// Transmit the given downlink
+    Forward(Packet),
+    /// The downlink of the given OUI lost against the downlink of the selected
+    /// OUI
+    Discard {
+        oui: u32,
+        packet: Packet,
+        selected: u32,
+    },
+}
+
+/// Selects a single downlink when the routers of several OUIs answer the same
+/// uplink. Uplinks are identified by their packet hash.
+pub struct DownlinkArbiter {
+    policy: DownlinkPolicy,
+    arbitrations: HashMap<Vec<u8>, Arbitration>,
+}
+
+struct Arbitration {
+    created: Instant,
+    /// The OUIs of routers that have not responded yet, one per router
+    waiting: Vec<u32>,
+    /// The best downlink so far while higher priority routers are waiting
+    held: Option<(u32, Packet)>,
+    /// The OUI of the downlink that was forwarded
+    selected: Option<u32>,
+}
+
+impl DownlinkArbiter {
+    pub fn new(policy: DownlinkPolicy) -> Self {
+        Self {
+            policy,
+            arbitrations: HashMap::new(),
+        }
+    }
+
+    /// Registers an uplink that was sent to routers of the given OUIs, with
+    /// one entry per router. Returns true if a downlink for the uplink may be
+    /// held back waiting for higher priority routers, in which case `timeout`
+    /// is expected to be called when the downlink can no longer wait.
+    pub fn uplink(&mut self, uplink: Vec<u8>, responders: Vec<u32>) -> bool {
+        self.arbitrations
+            .retain(|_, arbitration| arbitration.created.elapsed() < ARBITRATION_TTL);
+        if responders.len() < 2 {
+            return false;
+        }
+        self.arbitrations.insert(
+            uplink,
+            Arbitration {
+                created: Instant::now(),
+                waiting: responders,
+                held: None,
+                selected: None,
+            },
+        );
+        matches!(self.policy, DownlinkPolicy::OuiPriority { .. })
+    }
+
+    /// Handles the response of a router of the given OUI to an uplink, with
+    /// or without a downlink.
+    pub fn response(&mut self, uplink: &[u8], oui: u32, downlink: Option<Packet>) -> Vec<Outcome> {
+        let arbitration = match self.arbitrations.get_mut(uplink) {
+            Some(arbitration) => arbitration,
+            // Uplinks sent to a single router need no arbitration
+            None => return downlink.map(Outcome::Forward).into_iter().collect(),
+        };
+        if let Some(position) = arbitration.waiting.iter().position(|entry| *entry == oui) {
+            arbitration.waiting.swap_remove(position);
+        }
+        let mut outcomes = vec![];
+        if let Some(packet) = downlink {
+            match (arbitration.selected, &self.policy) {
+                (Some(selected), _) => outcomes.push(Outcome::Discard {
+                    oui,
+                    packet,
+                    selected,
+                }),
+                (None, DownlinkPolicy::FirstResponder) => {
+                    arbitration.selected = Some(oui);
+                    outcomes.push(Outcome::Forward(packet));
+                }
+                (None, DownlinkPolicy::OuiPriority { ouis }) => match arbitration.held.take() {
+                    Some((held_oui, held)) if rank(ouis, held_oui) <= rank(ouis, oui) => {
+                        outcomes.push(Outcome::Discard {
+                            oui,
+                            packet,
+                            selected: held_oui,
+                        });
+                        arbitration.held = Some((held_oui, held));
+                    }
+                    Some((held_oui, held)) => {
+                        outcomes.push(Outcome::Discard {
+                            oui: held_oui,
+                            packet: held,
+                            selected: oui,
+                        });
+                        arbitration.held = Some((oui, packet));
+                    }
+                    None => arbitration.held = Some((oui, packet)),
+                },
+            }
+        }
+        // Forward a held downlink once no router with a higher priority is
+        // still expected to answer
+        if let (Some((held_oui, _)), DownlinkPolicy::OuiPriority { ouis }) =
+            (&arbitration.held, &self.policy)
+        {
+            let held_rank = rank(ouis, *held_oui);
+            if arbitration
+                .waiting
+                .iter()
+                .all(|waiting| rank(ouis, *waiting) >= held_rank)
+            {
+                outcomes.extend(arbitration.forward_held());
+            }
+        }
+        outcomes
+    }
+
+    /// Forwards the downlink held for the given uplink, if any, since it can
+    /// not wait for other routers any longer.
+    pub fn timeout(&mut self, uplink: &[u8]) -> Option<Outcome> {
+        self.arbitrations
+            .get_mut(uplink)
+            .and_then(Arbitration::forward_held)
+    }
+}
+
+impl Arbitration {
+    fn forward_held(&mut self) -> Option<Outcome> {
+        let (oui, packet) = self.held.take()?;
+        self.selected = Some(oui);
+        Some(Outcome::Forward(packet))
+    }
+}
+
+/// The priority rank of an OUI, lower is better
+fn rank(ouis: &[u32], oui: u32) -> usize {
+    ouis.iter()
+        .position(|entry| *entry == oui)
+        .unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downlink(timestamp: u64) -> Option<Packet> {
+        Some(Packet::from(helium_proto::Packet {
+            timestamp,
+            ..Default::default()
+        }))
+    }
+
+    fn forwarded(outcomes: &[Outcome]) -> Vec<u64> {
+        outcomes
+            .iter()
+            .filter_map(|outcome| match outcome {
+                Outcome::Forward(packet) => Some(packet.timestamp),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_responder() {
+        let mut arbiter = DownlinkArbiter::new(DownlinkPolicy::FirstResponder);
+        assert!(!arbiter.uplink(vec![1], vec![1, 2]));
+        assert_eq!(vec![2], forwarded(&arbiter.response(&[1], 2, downlink(2))));
+        let outcomes = arbiter.response(&[1], 1, downlink(1));
+        assert!(matches!(
+            outcomes.as_slice(),
+            [Outcome::Discard {
+                oui: 1,
+                selected: 2,
+                ..
+            }]
+        ));
+        // Unknown uplinks are forwarded
+        assert_eq!(vec![3], forwarded(&arbiter.response(&[3], 3, downlink(3))));
+    }
+
+    #[test]
+    fn oui_priority() {
+        let mut arbiter = DownlinkArbiter::new(DownlinkPolicy::OuiPriority { ouis: vec![1, 2] });
+        assert!(arbiter.uplink(vec![1], vec![3, 2, 1]));
+        // Held while higher priority OUIs are waiting
+        assert!(forwarded(&arbiter.response(&[1], 3, downlink(3))).is_empty());
+        let outcomes = arbiter.response(&[1], 2, downlink(2));
+        assert!(matches!(
+            outcomes.as_slice(),
+            [Outcome::Discard {
+                oui: 3,
+                selected: 2,
+                ..
+            }]
+        ));
+        // OUI 1 answers without a downlink, releasing the held one
+        assert_eq!(vec![2], forwarded(&arbiter.response(&[1], 1, None)));
+
+        assert!(arbiter.uplink(vec![2], vec![2, 1]));
+        assert!(forwarded(&arbiter.response(&[2], 2, downlink(2))).is_empty());
+        assert!(matches!(arbiter.timeout(&[2]), Some(Outcome::Forward(_))));
+        assert!(matches!(
+            arbiter.response(&[2], 1, downlink(1)).as_slice(),
+            [Outcome::Discard { oui: 1, .. }]
+        ));
+    }
+
+    async fn forwarded_downlink(downlinks: &mut gateway::MessageReceiver) -> Option<u64> {
+        match downlinks.recv().await {
+            Some(gateway::Message::Downlink(packet)) => Some(packet.timestamp),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn arbiter_task() {
+        let (sender, messages) = message_channel(10);
+        let (downlinks, mut gateway_rx) = gateway::message_channel(10);
+        let (mirror, _) = mirror::message_channel(10);
+        let mut arbiter = Arbiter {
+            arbiter: DownlinkArbiter::new(DownlinkPolicy::OuiPriority { ouis: vec![1, 2] }),
+            messages,
+            sender: sender.clone(),
+            downlinks,
+            mirror,
+        };
+        let logger = Logger::root(slog::Discard, o!());
+        let (trigger, shutdown) = triggered::trigger();
+        let task = tokio::spawn(async move { arbiter.run(shutdown, &logger).await });
+
+        // Downlinks for unregistered uplinks are forwarded right away
+        sender
+            .response(2, vec![1], downlink(1))
+            .await
+            .expect("response");
+        assert_eq!(Some(1), forwarded_downlink(&mut gateway_rx).await);
+
+        // A held downlink is forwarded once the hold time passed
+        sender.uplink(vec![2], vec![2, 1]).await;
+        let sent = Instant::now();
+        sender
+            .response(2, vec![2], downlink(2))
+            .await
+            .expect("response");
+        assert_eq!(Some(2), forwarded_downlink(&mut gateway_rx).await);
+        assert!(sent.elapsed() >= DOWNLINK_HOLD_TIME);
+
+        trigger.trigger();
+        assert!(task.await.expect("arbiter task").is_ok());
+    }
+}
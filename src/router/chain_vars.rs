@@ -0,0 +1,195 @@
+use helium_proto::BlockchainVarV1;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// Maximum number of requested variables that are cached in addition to the
+/// fixed ones
+const MAX_REQUESTED_VARS: usize = 50;
+/// Requested variables that have not been requested for this long are no
+/// longer refreshed
+const REQUESTED_VAR_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+struct CachedVar {
+    var: BlockchainVarV1,
+    /// Chain height the variable was fetched at
+    height: u64,
+    /// When the variable was last requested
+    requested: Instant,
+}
+
+/// Chain variables fetched from validators, each with the chain height it was
+/// fetched at. Used to answer chain variable requests while no validator is
+/// connected.
+///
+/// The fixed variables are always kept up to date. Other variables are
+/// cached once a validator returned them for a request and are dropped when
+/// they are not requested for a while.
+#[derive(Debug, Default)]
+pub struct ChainVarCache {
+    /// Names of the variables to always keep up to date
+    fixed: HashSet<String>,
+    vars: HashMap<String, CachedVar>,
+}
+
+impl ChainVarCache {
+    /// Constructs an empty cache that keeps the given variables up to date
+    pub fn new<I, S>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        Self {
+            fixed: keys.into_iter().map(|key| key.to_string()).collect(),
+            vars: HashMap::new(),
+        }
+    }
+
+    /// The names of all variables to refresh
+    pub fn keys(&self) -> Vec<String> {
+        self.fixed
+            .iter()
+            .chain(self.vars.keys().filter(|key| !self.fixed.contains(*key)))
+            .cloned()
+            .collect()
+    }
+
+    /// Marks the cached variables of the given names as requested at the
+    /// given time
+    pub fn request(&mut self, keys: &[String], now: Instant) {
+        for key in keys {
+            if let Some(cached) = self.vars.get_mut(key) {
+                cached.requested = now;
+            }
+        }
+    }
+
+    /// Caches the given variables. Variables that are not cached yet are only
+    /// added while there is room for them.
+    pub fn insert(&mut self, vars: &[BlockchainVarV1], height: u64, now: Instant) {
+        for var in vars {
+            if let Some(cached) = self.vars.get_mut(&var.name) {
+                cached.var = var.clone();
+                cached.height = height;
+            } else if self.fixed.contains(&var.name) || self.requested_len() < MAX_REQUESTED_VARS {
+                self.vars.insert(
+                    var.name.clone(),
+                    CachedVar {
+                        var: var.clone(),
+                        height,
+                        requested: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Drops the given variables unless they are fixed, for example because
+    /// the validator rejects them
+    pub fn remove(&mut self, keys: &[String]) {
+        for key in keys {
+            if !self.fixed.contains(key) {
+                self.vars.remove(key);
+            }
+        }
+    }
+
+    /// Drops the variables that were not requested since the cache lifetime
+    /// for requested variables before the given time
+    pub fn expire(&mut self, now: Instant) {
+        let fixed = &self.fixed;
+        self.vars.retain(|key, cached| {
+            fixed.contains(key) || now.duration_since(cached.requested) < REQUESTED_VAR_TTL
+        });
+    }
+
+    /// Returns the cached values of the given variables, in order, and the
+    /// lowest height they were fetched at. Returns None unless all of the
+    /// variables are cached.
+    pub fn get(&self, keys: &[String]) -> Option<(Vec<BlockchainVarV1>, u64)> {
+        let mut height = u64::MAX;
+        let mut vars = Vec::with_capacity(keys.len());
+        for key in keys {
+            let cached = self.vars.get(key)?;
+            height = height.min(cached.height);
+            vars.push(cached.var.clone());
+        }
+        if vars.is_empty() {
+            height = 0;
+        }
+        Some((vars, height))
+    }
+
+    fn requested_len(&self) -> usize {
+        self.vars
+            .keys()
+            .filter(|key| !self.fixed.contains(*key))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> BlockchainVarV1 {
+        BlockchainVarV1 {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lowest_height() {
+        let now = Instant::now();
+        let mut cache = ChainVarCache::new(["a"]);
+        let keys = vec!["b".to_string(), "a".to_string()];
+        assert!(cache.get(&keys).is_none());
+        cache.insert(&[var("a")], 10, now);
+        assert!(cache.get(&keys).is_none());
+        cache.insert(&[var("b")], 12, now);
+        let (vars, height) = cache.get(&keys).expect("cached vars");
+        assert_eq!("b", vars[0].name);
+        assert_eq!(10, height);
+        assert_eq!(2, cache.keys().len());
+    }
+
+    #[test]
+    fn expire_requested() {
+        let now = Instant::now();
+        let mut cache = ChainVarCache::new(["a"]);
+        cache.insert(&[var("a"), var("b"), var("c")], 10, now);
+        assert_eq!(3, cache.keys().len());
+
+        let later = now + REQUESTED_VAR_TTL;
+        cache.request(&["b".to_string()], later - Duration::from_secs(1));
+        cache.expire(later);
+        let mut keys = cache.keys();
+        keys.sort();
+        assert_eq!(vec!["a", "b"], keys);
+
+        // Fixed variables are never dropped
+        cache.remove(&["a".to_string(), "b".to_string()]);
+        assert_eq!(vec!["a"], cache.keys());
+    }
+
+    #[test]
+    fn max_requested() {
+        let now = Instant::now();
+        let mut cache = ChainVarCache::new(["a"]);
+        let vars: Vec<BlockchainVarV1> = (0..MAX_REQUESTED_VARS + 10)
+            .map(|i| var(&format!("var_{i}")))
+            .collect();
+        cache.insert(&vars, 10, now);
+        assert_eq!(MAX_REQUESTED_VARS, cache.requested_len());
+        assert_eq!(MAX_REQUESTED_VARS + 1, cache.keys().len());
+        // Fixed variables are cached even when the cache is full
+        let fixed = vec!["a".to_string()];
+        assert!(cache.get(&fixed).is_none());
+        cache.insert(&[var("a")], 10, now);
+        assert!(cache.get(&fixed).is_some());
+        assert_eq!(MAX_REQUESTED_VARS + 1, cache.keys().len());
+    }
+}
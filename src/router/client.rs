@@ -1,16 +1,19 @@
 use crate::{
     error::Error,
     gateway,
-    router::{QuePacket, RouterStore},
-    service::router::RouterService,
+    router::{accounting::Counters, arbiter, QuePacket, RouterStore},
+    service::{router::RouterService, ConnectSettings},
     state_channel::StateChannelMessage,
     Base64, CacheSettings, KeyedUri, Keypair, Packet, Region, Result,
 };
 use futures::TryFutureExt;
 use slog::{debug, info, o, warn, Logger};
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 use tokio::{
-    sync::mpsc,
+    sync::{mpsc, watch},
     time::{self, Duration, MissedTickBehavior},
 };
 
@@ -19,8 +22,13 @@ pub const STATE_CHANNEL_CONNECT_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum Message {
-    Uplink { packet: Packet, received: Instant },
+    Uplink {
+        packet: Packet,
+        received: Instant,
+        arbitrated: bool,
+    },
     RegionChanged(Region),
+    CacheChanged(CacheSettings),
     Stop,
 }
 
@@ -38,9 +46,17 @@ impl MessageSender {
         let _ = self.0.send(Message::RegionChanged(region)).await;
     }
 
-    pub async fn uplink(&self, packet: Packet, received: Instant) -> Result {
+    pub async fn cache_changed(&self, settings: CacheSettings) {
+        let _ = self.0.send(Message::CacheChanged(settings)).await;
+    }
+
+    pub async fn uplink(&self, packet: Packet, received: Instant, arbitrated: bool) -> Result {
         self.0
-            .send(Message::Uplink { packet, received })
+            .send(Message::Uplink {
+                packet,
+                received,
+                arbitrated,
+            })
             .map_err(|_| Error::channel())
             .await
     }
@@ -50,36 +66,85 @@ impl MessageSender {
     }
 }
 
+/// The status of a router client as published to the dispatcher
+#[derive(Debug, Clone, Default)]
+pub struct RouterStatus {
+    /// Number of packets waiting to be delivered to the router
+    pub queued: usize,
+    /// The result of the last packet delivery, if any
+    pub last_send: Option<SendResult>,
+    /// Traffic counters, including the ones persisted from earlier runs
+    pub counters: Counters,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendResult {
+    pub time: SystemTime,
+    pub error: Option<String>,
+}
+
+impl RouterStatus {
+    /// A client is considered healthy until delivering a packet to its router
+    /// fails, and becomes healthy again on the next successful delivery.
+    pub fn is_healthy(&self) -> bool {
+        self.last_send
+            .as_ref()
+            .map_or(true, |result| result.error.is_none())
+    }
+}
+
 pub struct RouterClient {
     router: RouterService,
     oui: u32,
     region: Region,
     keypair: Arc<Keypair>,
     downlinks: gateway::MessageSender,
+    arbiter: arbiter::MessageSender,
     store: RouterStore,
+    status: watch::Sender<RouterStatus>,
 }
 
 impl RouterClient {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         oui: u32,
         region: Region,
         uri: KeyedUri,
         downlinks: gateway::MessageSender,
+        arbiter: arbiter::MessageSender,
         keypair: Arc<Keypair>,
         settings: CacheSettings,
+        connect_settings: &ConnectSettings,
+        counters: Counters,
     ) -> Result<Self> {
-        let router = RouterService::new(uri)?;
+        let router = RouterService::new(uri, connect_settings)?;
         let store = RouterStore::new(&settings);
+        let (status, _) = watch::channel(RouterStatus {
+            counters,
+            ..Default::default()
+        });
         Ok(Self {
             router,
             oui,
             region,
             keypair,
             downlinks,
+            arbiter,
             store,
+            status,
         })
     }
 
+    /// Returns a receiver for the status of this client
+    pub fn status(&self) -> watch::Receiver<RouterStatus> {
+        self.status.subscribe()
+    }
+
+    fn update_queued(&self) {
+        let queued = self.store.waiting_packets_len();
+        self.status.send_modify(|status| status.queued = queued);
+    }
+
     pub async fn run(
         &mut self,
         mut messages: MessageReceiver,
@@ -104,8 +169,8 @@ impl RouterClient {
                     return Ok(())
                 },
                 message = messages.recv() => match message {
-                    Some(Message::Uplink{packet, received}) => {
-                        self.handle_uplink(&logger, packet, received)
+                    Some(Message::Uplink{packet, received, arbitrated}) => {
+                        self.handle_uplink(&logger, packet, received, arbitrated)
                             .unwrap_or_else(|err| warn!(logger, "ignoring failed uplink {:?}", err))
                             .await;
                     },
@@ -114,6 +179,12 @@ impl RouterClient {
                         info!(logger, "updated region";
                             "region" => region);
                     },
+                    Some(Message::CacheChanged(settings)) => {
+                        self.store.set_max_packets(settings.max_packets);
+                        self.update_queued();
+                        info!(logger, "updated cache settings";
+                            "max_packets" => settings.max_packets);
+                    },
                     Some(Message::Stop) => {
                         info!(logger, "stop requested, shutting down");
                         return Ok(())
@@ -124,6 +195,7 @@ impl RouterClient {
                     let removed = self.store.gc_waiting_packets(STORE_GC_INTERVAL);
                     if removed > 0 {
                         info!(logger, "discarded {} queued packets", removed);
+                        self.update_queued();
                     }
                 }
             }
@@ -135,28 +207,69 @@ impl RouterClient {
         logger: &Logger,
         uplink: Packet,
         received: Instant,
+        arbitrated: bool,
     ) -> Result {
-        self.store.store_waiting_packet(uplink, received)?;
+        self.store
+            .store_waiting_packet(uplink, received, arbitrated)?;
+        self.update_queued();
         self.send_waiting_packets(logger).await
     }
 
-    async fn handle_downlink(&mut self, logger: &Logger, packet: Packet) {
-        let _ = self
-            .downlinks
-            .downlink(packet)
-            .inspect_err(|_| warn!(logger, "failed to push downlink"))
-            .await;
+    /// Sends the downlink for an uplink, if any, straight to the gateway. The
+    /// response to an uplink that was also sent to other routers is reported
+    /// to the arbiter instead, which selects the downlink to transmit.
+    async fn handle_response(&self, logger: &Logger, uplink: &QuePacket, packet: Option<Packet>) {
+        if uplink.arbitrated() {
+            let _ = self
+                .arbiter
+                .response(self.oui, uplink.hash(), packet)
+                .inspect_err(|_| warn!(logger, "failed to report router response"))
+                .await;
+        } else if let Some(packet) = packet {
+            let _ = self
+                .downlinks
+                .downlink(packet)
+                .inspect_err(|_| warn!(logger, "failed to push downlink"))
+                .await;
+        }
     }
 
     async fn send_waiting_packets(&mut self, logger: &Logger) -> Result {
         while let Some(packet) = self.store.pop_waiting_packet() {
-            if let Some(message) = self.send_packet(logger, &packet).await? {
-                match message.to_downlink() {
-                    Ok(Some(packet)) => self.handle_downlink(logger, packet).await,
-                    Ok(None) => (),
-                    Err(err) => warn!(logger, "ignoring router response: {err:?}"),
+            let sent = self.send_packet(logger, &packet).await;
+            let queued = self.store.waiting_packets_len();
+            let last_send = SendResult {
+                time: SystemTime::now(),
+                error: sent.as_ref().err().map(|err| err.to_string()),
+            };
+            let (downlink, result) = match sent {
+                Ok(Some(message)) => match message.to_downlink() {
+                    Ok(downlink) => (downlink, Ok(())),
+                    Err(err) => {
+                        warn!(logger, "ignoring router response: {err:?}");
+                        (None, Ok(()))
+                    }
+                },
+                Ok(None) => (None, Ok(())),
+                Err(err) => (None, Err(err)),
+            };
+            self.status.send_modify(|status| {
+                status.queued = queued;
+                status.last_send = Some(last_send);
+                let counters = &mut status.counters;
+                if result.is_ok() {
+                    counters.uplinks += 1;
+                    counters.bytes += packet.payload().len() as u64;
+                    counters.dc += packet.dc_payload();
+                } else {
+                    counters.failures += 1;
                 }
-            }
+                if downlink.is_some() {
+                    counters.downlinks += 1;
+                }
+            });
+            self.handle_response(logger, &packet, downlink).await;
+            result?;
         }
         Ok(())
     }
@@ -1,11 +1,27 @@
 use crate::{
-    gateway,
-    router::{self, RouterClient, Routing},
-    service::{self, gateway::GatewayService},
+    gateway, mirror, roaming,
+    router::{
+        self,
+        accounting::{Accounting, AccountingEntry, Counters},
+        arbiter,
+        chain_vars::ChainVarCache,
+        client::RouterStatus,
+        seeds::{self, SeedStore},
+        RouterClient, Routing, RoutingIndex,
+    },
+    service::{
+        self,
+        gateway::{GatewayProbe, GatewayService},
+        ConnectSettings,
+    },
+    settings::DefaultRouterPolicy,
+    status::DispatcherStatus,
     sync, CacheSettings, Error, KeyedUri, Keypair, Packet, Region, RegionParams, Result, Settings,
+    CONFIG_FEE_KEYS,
 };
 use exponential_backoff::Backoff;
 use futures::{
+    future,
     task::{Context, Poll},
     TryFutureExt,
 };
@@ -18,7 +34,7 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::{task::JoinHandle, time};
+use tokio::{sync::watch, task::JoinHandle, time};
 use tokio_stream::{self, StreamExt, StreamMap};
 
 #[derive(Debug)]
@@ -29,7 +45,7 @@ pub enum Message {
     },
     Config {
         keys: Vec<String>,
-        response: sync::ResponseSender<Result<Vec<BlockchainVarV1>>>,
+        response: sync::ResponseSender<Result<ConfigResponse>>,
     },
     Height {
         response: sync::ResponseSender<Result<HeightResponse>>,
@@ -37,6 +53,36 @@ pub enum Message {
     Region {
         response: sync::ResponseSender<Result<Region>>,
     },
+    Routers {
+        response: sync::ResponseSender<Result<Vec<RouterInfo>>>,
+    },
+    Accounting {
+        response: sync::ResponseSender<Result<Vec<AccountingEntry>>>,
+    },
+    ChainVars {
+        vars: Vec<BlockchainVarV1>,
+        height: u64,
+        /// Names of the variables the validator rejected
+        rejected: Vec<String>,
+    },
+    Seeds {
+        seeds: Vec<KeyedUri>,
+    },
+    SettingsChanged {
+        default_routers: Option<Vec<KeyedUri>>,
+        default_router_policy: DefaultRouterPolicy,
+        cache: CacheSettings,
+    },
+}
+
+/// Chain variables and the chain height they were fetched at
+#[derive(Debug)]
+pub struct ConfigResponse {
+    pub values: Vec<BlockchainVarV1>,
+    pub height: u64,
+    /// Whether the values were answered from the cache because they could
+    /// not be fetched from a validator
+    pub stale: bool,
 }
 
 #[derive(Debug)]
@@ -47,6 +93,18 @@ pub struct HeightResponse {
     pub gateway_version: u64,
 }
 
+/// A snapshot of a router client known to the dispatcher
+#[derive(Debug)]
+pub struct RouterInfo {
+    pub oui: u32,
+    pub uri: KeyedUri,
+    pub filters: usize,
+    pub subnets: usize,
+    /// Whether the router client task is still running
+    pub running: bool,
+    pub status: RouterStatus,
+}
+
 pub type MessageSender = sync::MessageSender<Message>;
 pub type MessageReceiver = sync::MessageReceiver<Message>;
 
@@ -55,7 +113,7 @@ pub fn message_channel(size: usize) -> (MessageSender, MessageReceiver) {
 }
 
 impl MessageSender {
-    pub async fn config(&self, keys: &[String]) -> Result<Vec<BlockchainVarV1>> {
+    pub async fn config(&self, keys: &[String]) -> Result<ConfigResponse> {
         let (tx, rx) = sync::response_channel();
         let _ = self
             .0
@@ -88,39 +146,127 @@ impl MessageSender {
         let _ = self.0.send(Message::Region { response: tx }).await;
         rx.recv().await?
     }
+
+    pub async fn accounting(&self) -> Result<Vec<AccountingEntry>> {
+        let (tx, rx) = sync::response_channel();
+        let _ = self.0.send(Message::Accounting { response: tx }).await;
+        rx.recv().await?
+    }
+
+    pub async fn routers(&self) -> Result<Vec<RouterInfo>> {
+        let (tx, rx) = sync::response_channel();
+        let _ = self.0.send(Message::Routers { response: tx }).await;
+        rx.recv().await?
+    }
+
+    /// Applies the default routers, default router policy and cache settings
+    /// of reloaded settings
+    pub async fn settings_changed(&self, settings: &Settings) {
+        let _ = self
+            .0
+            .send(Message::SettingsChanged {
+                default_routers: settings.routers.clone(),
+                default_router_policy: settings.default_router_policy,
+                cache: settings.cache.clone(),
+            })
+            .await;
+    }
 }
 
 pub struct Dispatcher {
     keypair: Arc<Keypair>,
     region: Region,
     messages: MessageReceiver,
+    sender: MessageSender,
     downlinks: gateway::MessageSender,
+    mirror: mirror::MessageSender,
+    roaming: roaming::MessageSender,
     seed_gateways: Vec<KeyedUri>,
+    /// The seed validators from the settings
+    configured_seeds: Vec<KeyedUri>,
+    seed_store: SeedStore,
+    /// Whether the seed validators are the persisted ones
+    persisted_seeds: bool,
+    seed_failures: u32,
+    seed_refresh_interval: Duration,
+    /// Validators that ranked best in the last selection
+    good_gateways: Vec<KeyedUri>,
+    /// A validator kept warm to fail over to when the active one fails
+    standby: Option<GatewayService>,
     routing_height: u64,
     region_height: u64,
+    /// Region params from a local channel plan file
+    local_region_params: Option<RegionParams>,
+    /// Whether region params from a validator replace the local ones
+    validator_override: bool,
     cache_settings: CacheSettings,
+    connect_settings: ConnectSettings,
     gateway_retry: u32,
     routers: HashMap<RouterKey, RouterEntry>,
+    routing_index: RoutingIndex<RouterKey>,
     default_routers: Option<Vec<KeyedUri>>,
+    default_router_policy: DefaultRouterPolicy,
+    default_router_next: usize,
+    static_routes: Vec<Routing>,
+    arbiter: arbiter::MessageSender,
+    accounting: Accounting,
+    chain_vars: ChainVarCache,
+    status: watch::Sender<DispatcherStatus>,
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 struct RouterKey {
+    source: RouteSource,
     oui: u32,
     uri: KeyedUri,
 }
 
+/// Where the routing of a router comes from. Routing updates only replace
+/// the routers of their own source, so chain routing for an OUI leaves the
+/// static routes and default routers alone.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum RouteSource {
+    Chain,
+    Default,
+    Static,
+}
+
 #[derive(Debug)]
 struct RouterEntry {
     routing: Routing,
     dispatch: router::client::MessageSender,
+    status: watch::Receiver<RouterStatus>,
     join_handle: JoinHandle<Result>,
 }
 
+impl RouterEntry {
+    fn is_healthy(&self) -> bool {
+        !self.join_handle.is_finished() && self.status.borrow().is_healthy()
+    }
+}
+
+/// The OUI used for the router clients of default routers
+const DEFAULT_ROUTER_OUI: u32 = 0;
+
 const GATEWAY_BACKOFF_RETRIES: u32 = 10;
 const GATEWAY_BACKOFF_MIN_WAIT: Duration = Duration::from_secs(5);
 const GATEWAY_BACKOFF_MAX_WAIT: Duration = Duration::from_secs(1800); // 30 minutes
 
+/// Number of validators to fetch from a seed as candidates for selection
+const GATEWAY_FETCH_COUNT: u32 = 5;
+/// Number of best ranked validators to remember as candidates for the next
+/// selection
+const GOOD_GATEWAYS: usize = 3;
+
+/// Interval to check the standby validator at, which also keeps its
+/// connection from going idle
+const STANDBY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of consecutive failed gateway selections from persisted seeds
+/// before falling back to the configured seeds
+const MAX_SEED_FAILURES: u32 = 3;
+/// Interval to refresh the cached chain variables at while connected
+const CHAIN_VAR_REFRESH_INTERVAL: Duration = Duration::from_secs(1800); // 30 minutes
 const GATEWAY_CHECK_INTERVAL: Duration = Duration::from_secs(900); // 15 minutes
 const GATEWAY_MAX_BLOCK_AGE: Duration = Duration::from_secs(1800); // 30 minutes
 
@@ -137,39 +283,122 @@ impl Dispatcher {
     #[allow(clippy::mutable_key_type)]
     pub fn new(
         messages: MessageReceiver,
+        sender: MessageSender,
         downlinks: gateway::MessageSender,
+        mirror: mirror::MessageSender,
+        roaming: roaming::MessageSender,
+        arbiter: arbiter::MessageSender,
         settings: &Settings,
     ) -> Result<Self> {
         let seed_gateways = settings.gateways.clone();
         let routers = HashMap::with_capacity(5);
         let default_routers = settings.routers.clone();
+        let static_routes = settings
+            .static_routes
+            .iter()
+            .map(Routing::from_settings)
+            .collect();
         let cache_settings = settings.cache.clone();
+        let local_region_params = settings
+            .region_params
+            .as_ref()
+            .map(|local| RegionParams::from_file(&local.path, settings.region))
+            .transpose()?;
         Ok(Self {
             keypair: settings.keypair.clone(),
-            region: settings.region,
+            region: local_region_params
+                .as_ref()
+                .map_or(settings.region, |params| params.region),
             messages,
+            sender,
             downlinks,
+            mirror,
+            roaming,
+            configured_seeds: seed_gateways.clone(),
             seed_gateways,
+            seed_store: SeedStore::new(settings.seeds.path.clone()),
+            persisted_seeds: false,
+            seed_failures: 0,
+            seed_refresh_interval: Duration::from_secs(settings.seeds.refresh_interval),
+            good_gateways: vec![],
+            standby: None,
             routers,
+            routing_index: RoutingIndex::default(),
             routing_height: 0,
             region_height: 0,
+            validator_override: settings
+                .region_params
+                .as_ref()
+                .map_or(true, |local| local.validator_override),
+            local_region_params,
             default_routers,
+            default_router_policy: settings.default_router_policy,
+            default_router_next: 0,
+            static_routes,
+            arbiter,
+            accounting: Accounting::new(&settings.accounting),
+            chain_vars: ChainVarCache::new(CONFIG_FEE_KEYS),
+            status: watch::channel(DispatcherStatus::default()).0,
             cache_settings,
+            connect_settings: ConnectSettings::from(settings),
             gateway_retry: 0,
         })
     }
 
+    /// Returns a receiver for the status of the validator connection
+    pub fn status(&self) -> watch::Receiver<DispatcherStatus> {
+        self.status.subscribe()
+    }
+
     pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
         let logger = logger.new(o!("module" => "dispatcher"));
+        self.accounting.load(&logger);
+        let result = self.run_dispatch(shutdown, &logger).await;
+        self.save_accounting(&logger);
+        result
+    }
+
+    async fn run_dispatch(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
         info!(logger, "starting"; 
             "region" => self.region);
 
-        if let Some(default_routers) = &self.default_routers {
-            for default_router in default_routers {
+        if let Some(region_params) = self.local_region_params.clone() {
+            info!(logger, "using local region params";
+                "region" => region_params.region,
+                "channels" => region_params.params.len(),
+                "validator_override" => self.validator_override);
+            self.downlinks.region_params_changed(region_params).await;
+            self.roaming.region_changed(self.region).await;
+        }
+
+        if let Some(default_routers) = self.default_routers.clone() {
+            for default_router in &default_routers {
                 info!(logger, "default router";
                     "pubkey" => default_router.pubkey.to_string(),
-                    "uri" => default_router.uri.to_string());
+                    "uri" => default_router.uri.to_string(),
+                    "policy" => format!("{:?}", self.default_router_policy));
             }
+            let routing = Routing::from_uris(DEFAULT_ROUTER_OUI, default_routers);
+            self.handle_oui_routing_update(RouteSource::Default, &routing, &shutdown, logger)
+                .await;
+        }
+
+        for routing in self.static_routes.clone() {
+            info!(logger, "static route";
+                "oui" => routing.oui,
+                "routers" => routing.uris.len());
+            self.handle_oui_routing_update(RouteSource::Static, &routing, &shutdown, logger)
+                .await;
+        }
+        self.rebuild_routing_index();
+
+        if self.seed_gateways.is_empty() {
+            info!(logger, "no gateways configured, using static routes only");
+            return self.run_without_gateway(shutdown, logger).await;
+        }
+        if let Some(seeds) = self.seed_store.load(logger) {
+            self.seed_gateways = seeds;
+            self.persisted_seeds = true;
         }
 
         let gateway_backoff = Backoff::new(
@@ -182,8 +411,32 @@ impl Dispatcher {
                 // Prevent unneeded seed reselection
                 return Ok(());
             }
+            // Switch over to the warm standby, keeping the routing and region
+            // heights
+            if let Some(standby) = self.standby.take() {
+                info!(logger, "failing over to standby gateway";
+                    "pubkey" => standby.uri.pubkey.to_string(),
+                    "uri" => standby.uri.uri.to_string());
+                tokio::select! {
+                    _ = shutdown.clone() => {
+                        info!(logger, "shutting down");
+                        return Ok(())
+                    },
+                    gateway = self.setup_gateway_streams(Some(standby), logger) => {
+                        if let Ok(Some((service, gateway_streams, default_region_params))) = gateway {
+                            self.validator_region_params(default_region_params).await;
+                            self.run_with_gateway(service, gateway_streams, shutdown.clone(), logger)
+                                .await?;
+                            self.prepare_gateway_change(&gateway_backoff, shutdown.clone(), logger)
+                                .await;
+                            continue;
+                        }
+                    }
+                }
+            }
             // Select seed
-            let seed_gateway = GatewayService::select_seed(&self.seed_gateways)?;
+            let seed_gateway =
+                GatewayService::select_seed(&self.seed_gateways, &self.connect_settings)?;
             info!(logger, "seed gateway";
                 "pubkey" => seed_gateway.uri.pubkey.to_string(),
                 "uri" => seed_gateway.uri.uri.to_string());
@@ -193,59 +446,244 @@ impl Dispatcher {
                         info!(logger, "shutting down");
                         return Ok(())
                     },
-                // Try to select a random validator from the seed and fetch the needed streams
-                gateway = Self::select_gateway(seed_gateway, &shutdown, &logger)
-                    .and_then(|service | self.setup_gateway_streams(service, &logger))
-                     => match gateway {
+                // Select the best validator from the seed and fetch the needed streams
+                gateway = async {
+                    let service = self.select_gateway(seed_gateway, logger).await?;
+                    self.setup_gateway_streams(service, logger).await
+                } => match gateway {
                         Ok(Some((service, gateway_streams, default_region_params))) => {
-                            self.downlinks.region_params_changed(default_region_params).await;
-                            self.run_with_gateway(service, gateway_streams,  shutdown.clone(), &logger)
+                            self.seed_failures = 0;
+                            self.validator_region_params(default_region_params).await;
+                            self.run_with_gateway(service, gateway_streams,  shutdown.clone(), logger)
                                 .await?;
                             },
                         Ok(None) =>
                             return Ok(()),
-                        Err(_err) => ()
+                        Err(_err) => self.seed_failed(logger),
                     }
             }
 
-            self.prepare_gateway_change(&gateway_backoff, shutdown.clone(), &logger)
+            self.prepare_gateway_change(&gateway_backoff, shutdown.clone(), logger)
                 .await;
         }
     }
 
+    /// Checks the standby validator, which also keeps its connection warm. A
+    /// failing or missing standby is replaced by the best remembered
+    /// validator other than the active one.
+    async fn check_standby(&mut self, active: &KeyedUri, logger: &Logger) {
+        let mut failed = None;
+        if let Some(standby) = self.standby.as_mut() {
+            match standby.probe().await {
+                Ok(probe) if probe.block_age <= GATEWAY_MAX_BLOCK_AGE.as_secs() => return,
+                Ok(_) | Err(_) => {
+                    warn!(logger, "standby gateway check failed";
+                        "pubkey" => standby.uri.pubkey.to_string(),
+                        "uri" => standby.uri.uri.to_string());
+                    failed = self.standby.take().map(|standby| standby.uri);
+                }
+            }
+        }
+        for uri in self.good_gateways.clone() {
+            if &uri == active || Some(&uri) == failed.as_ref() {
+                continue;
+            }
+            let mut candidate = match GatewayService::new(&uri, &self.connect_settings) {
+                Ok(candidate) => candidate,
+                Err(_) => continue,
+            };
+            match candidate.probe().await {
+                Ok(probe) if probe.block_age <= GATEWAY_MAX_BLOCK_AGE.as_secs() => {
+                    info!(logger, "standby gateway";
+                        "pubkey" => uri.pubkey.to_string(),
+                        "uri" => uri.uri.to_string());
+                    self.standby = Some(candidate);
+                    return;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    async fn run_without_gateway(
+        &mut self,
+        shutdown: triggered::Listener,
+        logger: &Logger,
+    ) -> Result {
+        loop {
+            tokio::select! {
+                _ = shutdown.clone() => {
+                    info!(logger, "shutting down");
+                    return Ok(())
+                },
+                message = self.messages.recv() => match message {
+                    Some(message) => self.handle_message(message, None, &shutdown, logger).await,
+                    None => {
+                        warn!(logger, "messages channel closed");
+                        return Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Selects the best validator from the validators returned by the seed
+    /// and the validators that were good in earlier selections. Candidates
+    /// are probed concurrently and ranked by round trip time, height lag,
+    /// version and block age.
     async fn select_gateway(
+        &mut self,
         mut seed_gateway: GatewayService,
-        shutdown: &triggered::Listener,
         logger: &Logger,
     ) -> Result<Option<GatewayService>> {
-        match seed_gateway.random_new(5, shutdown.clone()).await {
-            Ok(result) => Ok(result),
+        let mut candidates = match seed_gateway.validators(GATEWAY_FETCH_COUNT).await {
+            Ok(candidates) => candidates,
             Err(err) => {
                 warn!(logger, "gateway selection error: {err:?}";
                     "pubkey" => seed_gateway.uri.pubkey.to_string(),
                     "uri" => seed_gateway.uri.uri.to_string());
-                Err(err)
+                if self.good_gateways.is_empty() {
+                    return Err(err);
+                }
+                vec![]
             }
+        };
+        for good_gateway in &self.good_gateways {
+            if !candidates.contains(good_gateway) {
+                candidates.push(good_gateway.clone());
+            }
+        }
+        let probes = probe_gateways(candidates, &self.connect_settings, logger).await;
+        let ranked = GatewayProbe::rank(probes, GATEWAY_MAX_BLOCK_AGE.as_secs());
+        self.update_seeds(
+            ranked.iter().map(|gateway| gateway.uri.clone()).collect(),
+            logger,
+        );
+        self.good_gateways = ranked
+            .iter()
+            .take(GOOD_GATEWAYS)
+            .map(|gateway| gateway.uri.clone())
+            .collect();
+        // The runner up becomes the standby
+        let mut ranked = ranked.into_iter();
+        let selected = ranked.next();
+        self.standby = ranked.next();
+        selected
+            .map(Some)
+            .ok_or_else(|| Error::custom("no healthy gateway"))
+    }
+
+    /// Persists the given verified validators as seeds, merged with the
+    /// current persisted seeds, and uses them as seeds from now on.
+    fn update_seeds(&mut self, verified: Vec<KeyedUri>, logger: &Logger) {
+        if !self.seed_store.is_enabled() || verified.is_empty() {
+            return;
+        }
+        let current: &[KeyedUri] = if self.persisted_seeds {
+            &self.seed_gateways
+        } else {
+            &[]
+        };
+        let seeds = seeds::merge(verified, current);
+        self.seed_store.save(&seeds, logger);
+        self.seed_gateways = seeds;
+        self.persisted_seeds = true;
+    }
+
+    /// Counts a failed gateway selection from persisted seeds and falls back
+    /// to the configured seeds when the persisted ones keep failing.
+    fn seed_failed(&mut self, logger: &Logger) {
+        if !self.persisted_seeds {
+            return;
+        }
+        self.seed_failures += 1;
+        if self.seed_failures >= MAX_SEED_FAILURES {
+            warn!(logger, "persisted seed gateways unusable, using configured gateways";
+                "failures" => self.seed_failures);
+            self.seed_gateways = self.configured_seeds.clone();
+            self.persisted_seeds = false;
+            self.seed_failures = 0;
         }
     }
 
+    /// Refreshes the persisted seeds in the background with validators from
+    /// the given validator that pass a probe
+    fn refresh_seeds(&self, gateway: &GatewayService, logger: &Logger) {
+        if !self.seed_store.is_enabled() {
+            return;
+        }
+        let mut gateway = gateway.clone();
+        let connect_settings = self.connect_settings.clone();
+        let sender = self.sender.clone();
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            let candidates = match gateway.validators(seeds::MAX_SEEDS as u32).await {
+                Ok(candidates) => candidates,
+                Err(err) => {
+                    warn!(logger, "failed to fetch seed gateways: {err:?}");
+                    return;
+                }
+            };
+            let probes = probe_gateways(candidates, &connect_settings, &logger).await;
+            let seeds = GatewayProbe::rank(probes, GATEWAY_MAX_BLOCK_AGE.as_secs())
+                .into_iter()
+                .map(|gateway| gateway.uri)
+                .collect();
+            let _ = sender.0.send(Message::Seeds { seeds }).await;
+        });
+    }
+
     async fn setup_gateway_streams(
         &mut self,
         gateway: Option<GatewayService>,
         logger: &Logger,
-    ) -> Result<Option<(GatewayService, GatewayStreams, RegionParams)>> {
+    ) -> Result<Option<(GatewayService, GatewayStreams, Option<RegionParams>)>> {
         if gateway.is_none() {
             return Ok(None);
         }
         let mut gateway = gateway.unwrap();
+        // Streams resume from the last applied heights. A validator that is
+        // behind those heights can't serve the delta, so resync fully.
+        let (gateway_height, _) = gateway.height().await?;
+        if gateway_height < self.routing_height {
+            info!(logger, "gateway behind last routing height, resyncing";
+                "gateway_height" => gateway_height,
+                "routing_height" => self.routing_height);
+            self.routing_height = 0;
+            self.region_height = 0;
+        }
+        let resume_height = self.routing_height;
         let mut routing_gateway = gateway.clone();
-        let routing = routing_gateway.routing(self.routing_height);
-        let default_region_params = gateway
-            .region_params_for(&self.region, self.keypair.clone())
-            .await?;
+        let routing = async move {
+            match routing_gateway.routing(resume_height).await {
+                Err(err) if resume_height > 0 => {
+                    info!(logger, "gateway can't resume routing, resyncing: {err:?}";
+                        "routing_height" => resume_height);
+                    routing_gateway
+                        .routing(0)
+                        .await
+                        .map(|stream| (stream, true))
+                }
+                result => result.map(|stream| (stream, false)),
+            }
+        };
+        // The validator's default region params are not needed when only the
+        // local ones are used
+        let default_region_params = if self.local_region_params_only() {
+            None
+        } else {
+            Some(
+                gateway
+                    .region_params_for(&self.region, self.keypair.clone())
+                    .await?,
+            )
+        };
         let region_params = gateway.region_params(self.keypair.clone());
         match tokio::try_join!(routing, region_params) {
-            Ok((routing, region_params)) => {
+            Ok(((routing, resynced), region_params)) => {
+                if resynced {
+                    self.routing_height = 0;
+                }
                 let stream_map = StreamMap::from_iter([
                     (GatewayStream::Routing, routing),
                     (GatewayStream::RegionParams, region_params),
@@ -262,6 +700,22 @@ impl Dispatcher {
     }
 
     async fn run_with_gateway(
+        &mut self,
+        gateway: GatewayService,
+        streams: GatewayStreams,
+        shutdown: triggered::Listener,
+        logger: &Logger,
+    ) -> Result {
+        self.status
+            .send_modify(|status| status.validator = Some(gateway.uri.clone()));
+        let result = self
+            .run_gateway_streams(gateway, streams, shutdown, logger)
+            .await;
+        self.status.send_modify(|status| status.validator = None);
+        result
+    }
+
+    async fn run_gateway_streams(
         &mut self,
         mut gateway: GatewayService,
         mut streams: GatewayStreams,
@@ -271,9 +725,20 @@ impl Dispatcher {
         info!(logger, "using gateway";
             "pubkey" => gateway.uri.pubkey.to_string(),
             "uri" => gateway.uri.uri.to_string());
+        self.mirror
+            .status(mirror::StatusEvent::validator_changed(&gateway.uri));
 
-        // Initialize liveness check for gateway
+        // Initialize liveness check for gateway. The retry count is only
+        // reset once the gateway has stayed up for a full check interval
+        let connected = Instant::now();
         let mut gateway_check = time::interval(GATEWAY_CHECK_INTERVAL);
+        let mut standby_check = time::interval(STANDBY_CHECK_INTERVAL);
+        let mut chain_var_refresh = time::interval(CHAIN_VAR_REFRESH_INTERVAL);
+        // Seeds were just refreshed by the gateway selection
+        let mut seed_refresh = time::interval_at(
+            time::Instant::now() + self.seed_refresh_interval,
+            self.seed_refresh_interval,
+        );
         loop {
             tokio::select! {
                 _ = shutdown.clone() => {
@@ -299,15 +764,20 @@ impl Dispatcher {
                 },
                 _ = gateway_check.tick() => match self.check_gateway(&mut gateway, logger).await {
                     Ok(()) => {
-                        self.gateway_retry = 0
+                        if connected.elapsed() >= GATEWAY_CHECK_INTERVAL {
+                            self.gateway_retry = 0
+                        }
                     },
                     Err(err) => {
                         warn!(logger, "gateway check error: {err}");
                         return Ok(())
                     }
                 },
+                _ = standby_check.tick() => self.check_standby(&gateway.uri, logger).await,
+                _ = chain_var_refresh.tick() => self.refresh_chain_vars(&gateway, logger),
+                _ = seed_refresh.tick() => self.refresh_seeds(&gateway, logger),
                 message = self.messages.recv() => match message {
-                    Some(message) => self.handle_message(message, Some(&mut gateway.clone()), logger).await,
+                    Some(message) => self.handle_message(message, Some(&mut gateway.clone()), &shutdown, logger).await,
                     None => {
                         warn!(logger, "messages channel closed");
                         return Ok(())
@@ -318,7 +788,11 @@ impl Dispatcher {
     }
 
     async fn check_gateway(&mut self, gateway: &mut GatewayService, logger: &Logger) -> Result {
-        let (_, block_age) = gateway.height().await?;
+        let (height, block_age) = gateway.height().await?;
+        self.status.send_modify(|status| {
+            status.height = height;
+            status.block_age = block_age;
+        });
         info!(logger, "checking gateway"; 
             "pubkey" => gateway.uri.pubkey.to_string(),
             "block_age" => block_age);
@@ -342,46 +816,59 @@ impl Dispatcher {
             return;
         }
 
-        // Reset routing and region heigth for the next gateway
-        self.routing_height = 0;
-        self.region_height = 0;
+        // Routing and region heights are kept so the streams of the next
+        // gateway resume from them
 
-        // Use backof to sleep exponentially longer
-        self.gateway_retry += 1;
-        let sleep = backoff
-            .next(self.gateway_retry)
-            .unwrap_or(GATEWAY_BACKOFF_MAX_WAIT);
+        let sleep = self.next_gateway_wait(backoff);
+        if sleep.is_zero() {
+            return;
+        }
 
         // Select over either shutdown or sleep, and handle messages that don't
         // require a gateway
         info!(logger, "selecting new gateway in {}s", sleep.as_secs());
         tokio::select! {
-            _ = shutdown => {},
+            _ = shutdown.clone() => {},
             _ = time::sleep(sleep) => {}
             message = self.messages.recv() => match message {
-                Some(message) => self.handle_message(message, None, logger).await,
+                Some(message) => self.handle_message(message, None, &shutdown, logger).await,
                 None => warn!(logger, "ignoring closed messages channel"),
             }
         }
     }
 
+    /// Counts a gateway change as a retry and returns how long to wait
+    /// before it. Only the first change after a healthy gateway fails over
+    /// to the standby right away, so a gateway that keeps failing backs off
+    /// whether or not there is a standby.
+    fn next_gateway_wait(&mut self, backoff: &Backoff) -> Duration {
+        self.gateway_retry += 1;
+        if self.gateway_retry == 1 && self.standby.is_some() {
+            return Duration::ZERO;
+        }
+        // Use backof to sleep exponentially longer
+        backoff
+            .next(self.gateway_retry)
+            .unwrap_or(GATEWAY_BACKOFF_MAX_WAIT)
+    }
+
     async fn handle_message(
-        &self,
+        &mut self,
         message: Message,
         gateway: Option<&mut GatewayService>,
+        shutdown: &triggered::Listener,
         logger: &Logger,
     ) {
+        if self.accounting.should_save() {
+            self.save_accounting(logger);
+        }
         match message {
             Message::Uplink {
                 packet,
                 received_time,
             } => self.handle_uplink(&packet, received_time, logger).await,
             Message::Config { keys, response } => {
-                let reply = if let Some(gateway) = gateway {
-                    gateway.config(keys).await
-                } else {
-                    Err(Error::no_service())
-                };
+                let reply = self.handle_config(keys, gateway, logger).await;
                 response.send(reply, logger)
             }
             Message::Height { response } => {
@@ -402,30 +889,326 @@ impl Dispatcher {
                 response.send(reply, logger)
             }
             Message::Region { response } => response.send(Ok(self.region), logger),
+            Message::Routers { response } => response.send(Ok(self.router_infos()), logger),
+            Message::Accounting { response } => {
+                self.update_accounting();
+                response.send(Ok(self.accounting.entries()), logger)
+            }
+            Message::ChainVars {
+                vars,
+                height,
+                rejected,
+            } => {
+                if !rejected.is_empty() {
+                    info!(logger, "dropping rejected chain vars"; "keys" => rejected.join(","));
+                }
+                self.chain_vars.remove(&rejected);
+                self.chain_vars.insert(&vars, height, Instant::now());
+            }
+            Message::Seeds { seeds } => self.update_seeds(seeds, logger),
+            Message::SettingsChanged {
+                default_routers,
+                default_router_policy,
+                cache,
+            } => {
+                self.update_settings(
+                    default_routers,
+                    default_router_policy,
+                    cache,
+                    shutdown,
+                    logger,
+                )
+                .await
+            }
         }
     }
 
-    async fn handle_uplink(&self, packet: &Packet, received: Instant, logger: &Logger) {
-        let mut handled = false;
-        for router_entry in self.routers.values() {
-            if router_entry.routing.matches_routing_info(packet.routing()) {
-                match router_entry.dispatch.uplink(packet.clone(), received).await {
-                    Ok(()) => (),
-                    Err(err) => warn!(logger, "ignoring router dispatch error: {err:?}"),
+    /// Replaces the default routers, starting and stopping their router
+    /// clients as needed, and passes new cache settings on to all running
+    /// router clients.
+    async fn update_settings(
+        &mut self,
+        default_routers: Option<Vec<KeyedUri>>,
+        default_router_policy: DefaultRouterPolicy,
+        cache: CacheSettings,
+        shutdown: &triggered::Listener,
+        logger: &Logger,
+    ) {
+        if self.default_router_policy != default_router_policy {
+            info!(logger, "updated default router policy";
+                "policy" => format!("{default_router_policy:?}"));
+            self.default_router_policy = default_router_policy;
+            self.default_router_next = 0;
+        }
+        if self.cache_settings.max_packets != cache.max_packets {
+            info!(logger, "updated cache settings";
+                "max_packets" => cache.max_packets);
+            for entry in self.routers.values() {
+                entry.dispatch.cache_changed(cache.clone()).await;
+            }
+            self.cache_settings = cache;
+        }
+        if self.default_routers != default_routers {
+            let uris = default_routers.clone().unwrap_or_default();
+            for default_router in &uris {
+                info!(logger, "default router";
+                    "pubkey" => default_router.pubkey.to_string(),
+                    "uri" => default_router.uri.to_string(),
+                    "policy" => format!("{:?}", self.default_router_policy));
+            }
+            self.default_routers = default_routers;
+            self.default_router_next = 0;
+            let routing = Routing::from_uris(DEFAULT_ROUTER_OUI, uris);
+            self.handle_oui_routing_update(RouteSource::Default, &routing, shutdown, logger)
+                .await;
+            self.rebuild_routing_index();
+        }
+    }
+
+    /// Fetches the given chain variables from the validator, if any, and
+    /// falls back to the cached values when that fails.
+    async fn handle_config(
+        &mut self,
+        keys: Vec<String>,
+        gateway: Option<&mut GatewayService>,
+        logger: &Logger,
+    ) -> Result<ConfigResponse> {
+        self.chain_vars.request(&keys, Instant::now());
+        let err = match gateway {
+            Some(gateway) => match gateway.config(keys.clone()).await {
+                Ok((values, height)) => {
+                    self.chain_vars.insert(&values, height, Instant::now());
+                    return Ok(ConfigResponse {
+                        values,
+                        height,
+                        stale: false,
+                    });
                 }
-                handled = true;
+                Err(err) => err,
+            },
+            None => Error::no_service(),
+        };
+        match self.chain_vars.get(&keys) {
+            Some((values, height)) => {
+                debug!(logger, "answering config from cache: {err:?}"; "height" => height);
+                Ok(ConfigResponse {
+                    values,
+                    height,
+                    stale: true,
+                })
             }
+            None => Err(err),
+        }
+    }
+
+    /// Refreshes all cached chain variables from the given validator in the
+    /// background. When the validator rejects the request, the variables are
+    /// refreshed one by one to find the ones it rejects. A validator that
+    /// cannot be reached is not retried until the next refresh.
+    fn refresh_chain_vars(&mut self, gateway: &GatewayService, logger: &Logger) {
+        self.chain_vars.expire(Instant::now());
+        let keys = self.chain_vars.keys();
+        if keys.is_empty() {
+            return;
         }
-        if !handled {
-            if let Some(default_routers) = &self.default_routers {
-                for (router_key, router_entry) in &self.routers {
-                    if default_routers.contains(&router_key.uri) {
-                        debug!(logger, "sending to default router");
-                        let _ = router_entry.dispatch.uplink(packet.clone(), received).await;
+        let mut gateway = gateway.clone();
+        let sender = self.sender.clone();
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            let message = match gateway.config(keys.clone()).await {
+                Ok((vars, height)) => Message::ChainVars {
+                    vars,
+                    height,
+                    rejected: vec![],
+                },
+                Err(err) if err.is_transport() => {
+                    warn!(logger, "failed to refresh chain vars: {err:?}");
+                    return;
+                }
+                Err(err) => {
+                    warn!(logger, "failed to refresh chain vars: {err:?}");
+                    let mut vars = Vec::with_capacity(keys.len());
+                    let mut height = u64::MAX;
+                    let mut rejected = vec![];
+                    for key in keys {
+                        match gateway.config(vec![key.clone()]).await {
+                            Ok((mut values, key_height)) => {
+                                vars.append(&mut values);
+                                height = height.min(key_height);
+                            }
+                            Err(err) if err.is_transport() => {
+                                warn!(logger, "stopping chain var refresh: {err:?}");
+                                break;
+                            }
+                            Err(_) => rejected.push(key),
+                        }
+                    }
+                    // Nothing was refreshed, assume the validator is not
+                    // reachable rather than rejecting all variables
+                    if vars.is_empty() {
+                        return;
+                    }
+                    Message::ChainVars {
+                        vars,
+                        height,
+                        rejected,
                     }
                 }
+            };
+            let _ = sender.0.send(message).await;
+        });
+    }
+
+    /// Updates the accounting with the current counters of all routers
+    fn update_accounting(&mut self) {
+        for (router_key, router_entry) in &self.routers {
+            let counters = router_entry.status.borrow().counters;
+            self.accounting
+                .update(router_key.oui, &router_key.uri, counters);
+        }
+    }
+
+    fn save_accounting(&mut self, logger: &Logger) {
+        self.update_accounting();
+        self.accounting.save(logger);
+    }
+
+    async fn handle_uplink(&mut self, packet: &Packet, received: Instant, logger: &Logger) {
+        let mut ouis = vec![];
+        let mut limited: Vec<u32> = vec![];
+        let mut router_entries = vec![];
+        for router_key in self.routing_index.matches(packet.routing()) {
+            if limited.contains(&router_key.oui) {
+                continue;
+            }
+            let routers = &self.routers;
+            if !ouis.contains(&router_key.oui)
+                && !self
+                    .accounting
+                    .allow(router_key.oui, || oui_counters(routers, router_key.oui))
+            {
+                debug!(logger, "oui limit reached"; "oui" => router_key.oui);
+                limited.push(router_key.oui);
+                continue;
+            }
+            if let Some(router_entry) = self.routers.get(router_key) {
+                router_entries.push(router_entry);
+                ouis.push(router_key.oui);
+            }
+        }
+        // The arbitration is registered before the routers can answer
+        let arbitrated = self.arbitrate_downlinks(packet.hash(), ouis.clone()).await;
+        for router_entry in router_entries {
+            match router_entry
+                .dispatch
+                .uplink(packet.clone(), received, arbitrated)
+                .await
+            {
+                Ok(()) => (),
+                Err(err) => warn!(logger, "ignoring router dispatch error: {err:?}"),
             }
         }
+        // Packets for limited OUIs are dropped rather than sent elsewhere
+        let default_routers = if ouis.is_empty() && limited.is_empty() {
+            // Packets for roaming partners are not sent to default routers
+            if self.roaming.uplink(packet, received).await {
+                self.mirror.uplink(packet, ouis, vec![]);
+                return;
+            }
+            self.handle_default_uplink(packet, received, logger).await
+        } else {
+            ouis.sort_unstable();
+            ouis.dedup();
+            vec![]
+        };
+        self.mirror.uplink(packet, ouis, default_routers);
+    }
+
+    /// Registers an uplink sent to several routers, one OUI per router, with
+    /// the arbiter. Returns whether the routers report their responses to the
+    /// arbiter, otherwise their downlinks go straight to the gateway.
+    async fn arbitrate_downlinks(&self, uplink: Vec<u8>, responders: Vec<u32>) -> bool {
+        if responders.len() < 2 {
+            return false;
+        }
+        self.arbiter.uplink(uplink, responders).await;
+        true
+    }
+
+    /// Sends the given packet to the default routers selected by the default
+    /// router policy. Returns the uris of the routers the packet was sent to.
+    async fn handle_default_uplink(
+        &mut self,
+        packet: &Packet,
+        received: Instant,
+        logger: &Logger,
+    ) -> Vec<String> {
+        let default_routers = match &self.default_routers {
+            Some(default_routers) if !default_routers.is_empty() => default_routers,
+            _ => return vec![],
+        };
+        let entries: Vec<(&KeyedUri, &RouterEntry)> = default_routers
+            .iter()
+            .filter_map(|uri| {
+                self.routers
+                    .get(&RouterKey {
+                        source: RouteSource::Default,
+                        oui: DEFAULT_ROUTER_OUI,
+                        uri: uri.clone(),
+                    })
+                    .map(|entry| (uri, entry))
+            })
+            .collect();
+        if entries.is_empty() {
+            return vec![];
+        }
+        let selected = match self.default_router_policy {
+            DefaultRouterPolicy::All => entries,
+            DefaultRouterPolicy::FirstHealthy => {
+                // Fall back to the first router if none are known to be healthy
+                let entry = entries
+                    .iter()
+                    .find(|(_, entry)| entry.is_healthy())
+                    .unwrap_or(&entries[0]);
+                vec![*entry]
+            }
+            DefaultRouterPolicy::RoundRobin => {
+                let index = self.default_router_next % entries.len();
+                self.default_router_next = index + 1;
+                vec![entries[index]]
+            }
+        };
+        let arbitrated = self
+            .arbitrate_downlinks(packet.hash(), vec![DEFAULT_ROUTER_OUI; selected.len()])
+            .await;
+        let mut uris = Vec::with_capacity(selected.len());
+        for (uri, router_entry) in selected {
+            debug!(logger, "sending to default router";
+                "uri" => uri.uri.to_string());
+            if let Err(err) = router_entry
+                .dispatch
+                .uplink(packet.clone(), received, arbitrated)
+                .await
+            {
+                warn!(logger, "ignoring default router dispatch error: {err:?}")
+            }
+            uris.push(uri.uri.to_string());
+        }
+        uris
+    }
+
+    /// Whether region params from a validator are ignored in favor of the
+    /// local region params
+    fn local_region_params_only(&self) -> bool {
+        self.local_region_params.is_some() && !self.validator_override
+    }
+
+    /// Passes the default region params fetched from a new validator, if
+    /// any, on to the downlink handler
+    async fn validator_region_params(&self, region_params: Option<RegionParams>) {
+        if let Some(region_params) = region_params {
+            self.downlinks.region_params_changed(region_params).await;
+        }
     }
 
     async fn handle_region_params_update<R: service::gateway::Response>(
@@ -435,13 +1218,24 @@ impl Dispatcher {
     ) {
         let update_height = response.height();
         let current_height = self.region_height;
-        if update_height <= self.region_height {
+        if update_height == current_height {
+            // A resumed stream resends the region params at the current height
+            debug!(logger, "region_params already at height {update_height}");
+            return;
+        }
+        if update_height < current_height {
             warn!(
                 logger,
                 "region_params returned invalid height {update_height} while at {current_height}"
             );
             return;
         }
+        if self.local_region_params_only() {
+            self.region_height = update_height;
+            debug!(logger, "ignoring validator region_params for local region params";
+                "height" => update_height);
+            return;
+        }
         match response.region_params() {
             Ok(region_params) => {
                 self.region_height = update_height;
@@ -459,6 +1253,7 @@ impl Dispatcher {
                 for router_entry in self.routers.values() {
                     router_entry.dispatch.region_changed(self.region).await;
                 }
+                self.roaming.region_changed(self.region).await;
             }
             Err(err) => {
                 warn!(logger, "error decoding region: {err:?}");
@@ -474,7 +1269,11 @@ impl Dispatcher {
     ) {
         let update_height = response.height();
         let current_height = self.routing_height;
-        if update_height <= self.routing_height {
+        if update_height == current_height {
+            debug!(logger, "routing already at height {update_height}");
+            return;
+        }
+        if update_height < current_height {
             warn!(
                 logger,
                 "routing returned invalid height {update_height} while at {current_height}",
@@ -492,19 +1291,31 @@ impl Dispatcher {
         while let Some(proto) = proto_stream.next().await {
             match Routing::from_proto(logger, proto) {
                 Ok(routing) => {
-                    self.handle_oui_routing_update(&routing, shutdown, logger)
+                    self.handle_oui_routing_update(RouteSource::Chain, &routing, shutdown, logger)
                         .await
                 }
                 Err(err) => warn!(logger, "failed to parse routing: {err:?}"),
             }
         }
+        self.rebuild_routing_index();
         self.routing_height = update_height;
         info!(logger, "updated routing to height {:?}", update_height)
     }
 
+    /// Rebuilds the index used to look up the routers for an uplink from the
+    /// routing of all current routers
+    fn rebuild_routing_index(&mut self) {
+        self.routing_index = RoutingIndex::new(
+            self.routers
+                .iter()
+                .map(|(router_key, router_entry)| (router_key.clone(), &router_entry.routing)),
+        );
+    }
+
     #[allow(clippy::map_entry)]
     async fn handle_oui_routing_update(
         &mut self,
+        source: RouteSource,
         routing: &Routing,
         shutdown: &triggered::Listener,
         logger: &Logger,
@@ -512,9 +1323,23 @@ impl Dispatcher {
         let mut uris = tokio_stream::iter(routing.uris.iter());
         while let Some(uri) = uris.next().await {
             let key = RouterKey {
+                source,
                 oui: routing.oui,
                 uri: uri.to_owned(),
             };
+            // Static routes are set up first and take precedence over chain
+            // routing for the same router, so its packets are only delivered
+            // once
+            let static_key = RouterKey {
+                source: RouteSource::Static,
+                ..key.clone()
+            };
+            if source == RouteSource::Chain && self.routers.contains_key(&static_key) {
+                debug!(logger, "using static route for router";
+                    "oui" => key.oui,
+                    "uri" => key.uri.uri.to_string());
+                continue;
+            }
             // We have to allow clippy::map_entry above since we need to borrow
             // immutable before borrowing as mutable to insert
             match self.routers.get_mut(&key) {
@@ -532,16 +1357,20 @@ impl Dispatcher {
                 },
             }
         }
-        // Remove any routers that are not in the new oui uri list
+        // Remove any routers of the same source that are not in the new oui
+        // uri list
         let mut removables = Vec::with_capacity(self.routers.len());
         self.routers.retain(|key, entry| {
-            if key.oui == routing.oui && !entry.routing.contains_uri(&key.uri) {
+            if key.source == source && key.oui == routing.oui && !routing.contains_uri(&key.uri) {
                 // Router will be removed from the map. The router is expected
                 // to stop itself when it receives the stop message
                 info!(logger, "removing router";
                     "oui" => key.oui,
                     "uri" => key.uri.uri.to_string()
                 );
+                // Keep the counters of the removed router
+                let counters = entry.status.borrow().counters;
+                self.accounting.update(key.oui, &key.uri, counters);
                 removables.push(entry.dispatch.clone());
                 return false;
             }
@@ -562,23 +1391,89 @@ impl Dispatcher {
         // previously set KV pairs (which causes dupes)
         let logger = slog_scope::logger();
         let (client_tx, client_rx) = router::client::message_channel(10);
+        let counters = self.accounting.counters(routing.oui, &uri);
         let mut client = RouterClient::new(
             routing.oui,
             self.region,
             uri,
             self.downlinks.clone(),
+            self.arbiter.clone(),
             self.keypair.clone(),
             self.cache_settings.clone(),
+            &self.connect_settings,
+            counters,
         )
         .await?;
+        let status = client.status();
         let join_handle =
             tokio::spawn(async move { client.run(client_rx, shutdown, &logger).await });
         Ok(RouterEntry {
             routing,
             dispatch: client_tx,
+            status,
             join_handle,
         })
     }
+
+    fn router_infos(&self) -> Vec<RouterInfo> {
+        let mut infos: Vec<RouterInfo> = self
+            .routers
+            .iter()
+            .map(|(router_key, router_entry)| RouterInfo {
+                oui: router_key.oui,
+                uri: router_key.uri.clone(),
+                filters: router_entry.routing.filters_len(),
+                subnets: router_entry.routing.subnets_len(),
+                running: !router_entry.join_handle.is_finished(),
+                status: router_entry.status.borrow().clone(),
+            })
+            .collect();
+        infos.sort_unstable_by_key(|info| info.oui);
+        infos
+    }
+}
+
+/// Probes the given validators concurrently, returning the ones that answered
+/// along with their probe results
+async fn probe_gateways(
+    candidates: Vec<KeyedUri>,
+    connect_settings: &ConnectSettings,
+    logger: &Logger,
+) -> Vec<(GatewayService, GatewayProbe)> {
+    let probes = future::join_all(candidates.into_iter().map(|uri| async move {
+        let mut gateway = GatewayService::new(&uri, connect_settings)?;
+        let probe = gateway.probe().await?;
+        Ok::<_, Error>((gateway, probe))
+    }))
+    .await;
+    probes
+        .into_iter()
+        .filter_map(|probe| match probe {
+            Ok((gateway, probe)) => {
+                debug!(logger, "probed gateway";
+                    "pubkey" => gateway.uri.pubkey.to_string(),
+                    "uri" => gateway.uri.uri.to_string(),
+                    "rtt_ms" => probe.rtt.as_millis() as u64,
+                    "height" => probe.height,
+                    "block_age" => probe.block_age,
+                    "version" => probe.version);
+                Some((gateway, probe))
+            }
+            Err(err) => {
+                debug!(logger, "gateway probe error: {err:?}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// The current counters of all current routers of an OUI
+fn oui_counters(routers: &HashMap<RouterKey, RouterEntry>, oui: u32) -> Vec<(&KeyedUri, Counters)> {
+    routers
+        .iter()
+        .filter(|(router_key, _)| router_key.oui == oui)
+        .map(|(router_key, router_entry)| (&router_key.uri, router_entry.status.borrow().counters))
+        .collect()
 }
 
 impl std::future::Future for RouterEntry {
@@ -591,3 +1486,157 @@ impl std::future::Future for RouterEntry {
         Pin::new(&mut self.join_handle).poll(cxt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, File};
+    use std::{env, process};
+
+    fn test_settings(name: &str) -> Settings {
+        let keypair = env::temp_dir().join(format!("dispatcher_{name}_{}.key", process::id()));
+        Config::builder()
+            .add_source(File::with_name("config/default.toml"))
+            .set_override("keypair", keypair.to_string_lossy().to_string())
+            .and_then(|builder| builder.build())
+            .and_then(|config| config.try_deserialize())
+            .expect("test settings")
+    }
+
+    fn test_dispatcher(settings: &Settings) -> Dispatcher {
+        let (sender, messages) = message_channel(10);
+        let (downlinks, _) = gateway::message_channel(10);
+        let (mirror, _) = mirror::message_channel(10);
+        let (roaming, _) = roaming::message_channel(settings, 10);
+        let (arbiter, _) = arbiter::message_channel(10);
+        Dispatcher::new(
+            messages, sender, downlinks, mirror, roaming, arbiter, settings,
+        )
+        .expect("dispatcher")
+    }
+
+    fn default_router_key(uri: &KeyedUri) -> RouterKey {
+        RouterKey {
+            source: RouteSource::Default,
+            oui: DEFAULT_ROUTER_OUI,
+            uri: uri.clone(),
+        }
+    }
+
+    /// Waits for the router client behind the given status to stop
+    async fn stopped(mut status: watch::Receiver<RouterStatus>) -> bool {
+        time::timeout(Duration::from_secs(1), async {
+            while status.changed().await.is_ok() {}
+        })
+        .await
+        .is_ok()
+    }
+
+    #[tokio::test]
+    async fn removed_default_router_is_stopped() {
+        let settings = test_settings("removed_default_router");
+        let mut dispatcher = test_dispatcher(&settings);
+        let logger = Logger::root(slog::Discard, o!());
+        let (_trigger, shutdown) = triggered::trigger();
+        let uris = settings.routers.clone().expect("default routers");
+        assert_eq!(2, uris.len());
+
+        let routing = Routing::from_uris(DEFAULT_ROUTER_OUI, uris.clone());
+        dispatcher
+            .handle_oui_routing_update(RouteSource::Default, &routing, &shutdown, &logger)
+            .await;
+        assert_eq!(2, dispatcher.routers.len());
+        let removed = dispatcher.routers[&default_router_key(&uris[1])]
+            .status
+            .clone();
+
+        dispatcher
+            .update_settings(
+                Some(vec![uris[0].clone()]),
+                settings.default_router_policy,
+                settings.cache.clone(),
+                &shutdown,
+                &logger,
+            )
+            .await;
+        assert_eq!(1, dispatcher.routers.len());
+        assert!(dispatcher
+            .routers
+            .contains_key(&default_router_key(&uris[0])));
+        assert!(stopped(removed).await);
+
+        dispatcher
+            .update_settings(
+                None,
+                settings.default_router_policy,
+                settings.cache.clone(),
+                &shutdown,
+                &logger,
+            )
+            .await;
+        assert!(dispatcher.routers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn chain_routing_leaves_static_routes() {
+        let settings = test_settings("chain_routing");
+        let mut dispatcher = test_dispatcher(&settings);
+        let logger = Logger::root(slog::Discard, o!());
+        let (_trigger, shutdown) = triggered::trigger();
+        let uris = settings.routers.clone().expect("default routers");
+        let static_key = RouterKey {
+            source: RouteSource::Static,
+            oui: 5,
+            uri: uris[0].clone(),
+        };
+
+        let routing = Routing::from_uris(5, vec![uris[0].clone()]);
+        dispatcher
+            .handle_oui_routing_update(RouteSource::Static, &routing, &shutdown, &logger)
+            .await;
+        // Chain routing for the same OUI and router leaves the static route
+        // as the only client of the router
+        let routing = Routing::from_uris(5, vec![uris[0].clone(), uris[1].clone()]);
+        dispatcher
+            .handle_oui_routing_update(RouteSource::Chain, &routing, &shutdown, &logger)
+            .await;
+        assert_eq!(2, dispatcher.routers.len());
+        assert_eq!(1, dispatcher.routers[&static_key].routing.uris.len());
+        assert!(!dispatcher.routers.contains_key(&RouterKey {
+            source: RouteSource::Chain,
+            ..static_key.clone()
+        }));
+
+        // Removing the OUI from the chain leaves the static route running
+        let routing = Routing::from_uris(5, vec![]);
+        dispatcher
+            .handle_oui_routing_update(RouteSource::Chain, &routing, &shutdown, &logger)
+            .await;
+        assert_eq!(1, dispatcher.routers.len());
+        assert!(dispatcher.routers.contains_key(&static_key));
+    }
+
+    #[tokio::test]
+    async fn repeated_gateway_failures_back_off_with_standby() {
+        let settings = test_settings("gateway_backoff");
+        let mut dispatcher = test_dispatcher(&settings);
+        let backoff = Backoff::new(
+            GATEWAY_BACKOFF_RETRIES,
+            GATEWAY_BACKOFF_MIN_WAIT,
+            GATEWAY_BACKOFF_MAX_WAIT,
+        );
+        let standby = GatewayService::new(&settings.gateways[0], &dispatcher.connect_settings)
+            .expect("standby gateway");
+
+        // The first failure after a healthy gateway fails over right away
+        dispatcher.standby = Some(standby.clone());
+        assert_eq!(Duration::ZERO, dispatcher.next_gateway_wait(&backoff));
+
+        // A stream that keeps failing backs off even with a fresh standby
+        for retry in 2..5 {
+            dispatcher.standby = Some(standby.clone());
+            assert!(!dispatcher.next_gateway_wait(&backoff).is_zero());
+            assert_eq!(retry, dispatcher.gateway_retry);
+        }
+    }
+}
@@ -1,17 +1,36 @@
+use crate::{Error, Result};
 use bytes::{Buf, BufMut};
 use helium_proto::Eui;
+use serde::Deserialize;
 use std::{fmt, sync::Arc};
 use xorf::{Filter as XorFilter, Xor16};
 use xxhash_rust::xxh64::Xxh64;
 
 #[derive(Clone)]
 pub struct EuiFilter(Arc<Xor16>);
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "DevAddrSubnet")]
 pub struct DevAddrFilter {
     base: u32,
     size: u32,
 }
 
+/// A devaddr subnet as given in the settings, which is validated when
+/// converted into a `DevAddrFilter`
+#[derive(Deserialize)]
+struct DevAddrSubnet {
+    base: u32,
+    size: u32,
+}
+
+impl TryFrom<DevAddrSubnet> for DevAddrFilter {
+    type Error = Error;
+
+    fn try_from(subnet: DevAddrSubnet) -> Result<Self> {
+        Self::new(subnet.base, subnet.size)
+    }
+}
+
 impl fmt::Debug for EuiFilter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EuiFilter")
@@ -38,23 +57,99 @@ impl EuiFilter {
         }))
     }
 
+    /// Serializes the filter in the same binary format as accepted by
+    /// `from_bin`.
+    pub fn to_bin(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16 + self.0.fingerprints.len() * 2);
+        buf.put_u64_le(self.0.seed);
+        buf.put_u64_le(self.0.block_length as u64);
+        for fingerprint in self.0.fingerprints.iter() {
+            buf.put_u16_le(*fingerprint);
+        }
+        buf
+    }
+
+    /// Constructs a filter from a list of (DevEUI, AppEUI) pairs. Returns None
+    /// if the given list is empty.
+    pub fn from_pairs(pairs: &[(u64, u64)]) -> Option<Self> {
+        let euis: Vec<Eui> = pairs
+            .iter()
+            .map(|(deveui, appeui)| Eui {
+                deveui: *deveui,
+                appeui: *appeui,
+            })
+            .collect();
+        Self::from_euis(&euis)
+    }
+
+    /// Constructs a filter that contains the given list of EUIs. Returns None
+    /// if the given list is empty.
+    pub fn from_euis(euis: &[Eui]) -> Option<Self> {
+        let mut hashes: Vec<u64> = euis.iter().map(eui_hash).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        if hashes.is_empty() {
+            return None;
+        }
+        Some(Self(Arc::new(Xor16::from(hashes.as_slice()))))
+    }
+
     pub fn contains(&self, eui: &Eui) -> bool {
-        let Eui { deveui, appeui } = eui;
-        let mut data = [0u8; 16];
-        let mut buf = &mut data[..];
-        buf.put_u64_le(*deveui);
-        buf.put_u64_le(*appeui);
-        let mut hasher = Xxh64::new(0);
-        hasher.update(&data);
-        let hash = hasher.digest();
+        self.contains_hash(eui_hash(eui))
+    }
+
+    /// Checks for an EUI by its hash as returned by `eui_hash`. This avoids
+    /// rehashing an EUI when checking it against many filters.
+    pub fn contains_hash(&self, hash: u64) -> bool {
         self.0.contains(&hash)
     }
 }
 
+/// The hash of an EUI as stored in an `EuiFilter`
+pub fn eui_hash(eui: &Eui) -> u64 {
+    let Eui { deveui, appeui } = eui;
+    let mut data = [0u8; 16];
+    let mut buf = &mut data[..];
+    buf.put_u64_le(*deveui);
+    buf.put_u64_le(*appeui);
+    let mut hasher = Xxh64::new(0);
+    hasher.update(&data);
+    hasher.digest()
+}
+
 const BITS_23: u64 = 8388607; // biggest unsigned number in 23 bits
 const BITS_25: u64 = 33554431; // biggest unsigned number in 25 bits
 
+impl fmt::Display for DevAddrFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#08x}/{}", self.base, self.size)
+    }
+}
+
 impl DevAddrFilter {
+    /// Constructs a filter for the subnet of the given size starting at the
+    /// given base address. The size must be a power of two of at least 8 and
+    /// the base must be aligned to the size.
+    pub fn new(base: u32, size: u32) -> Result<Self> {
+        if size < 8 || !size.is_power_of_two() {
+            return Err(Error::custom(format!("invalid devaddr subnet size {size}")));
+        }
+        if base % size != 0 || (base as u64 + size as u64) > BITS_23 + 1 {
+            return Err(Error::custom(format!(
+                "invalid devaddr subnet base {base} for size {size}"
+            )));
+        }
+        Ok(Self { base, size })
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
     pub fn from_bin<D: AsRef<[u8]>>(data: D) -> Self {
         let mut buf = [0u8; 8];
         buf[2..].copy_from_slice(data.as_ref());
@@ -65,10 +160,24 @@ impl DevAddrFilter {
         Self { base, size }
     }
 
+    /// Serializes the filter in the same binary format as accepted by
+    /// `from_bin`.
+    pub fn to_bin(&self) -> Vec<u8> {
+        let mask = BITS_23 ^ (((self.size - 4) >> 2) as u64 & BITS_23);
+        let val = ((self.base as u64 & BITS_25) << 23) | mask;
+        val.to_be_bytes()[2..].to_vec()
+    }
+
     pub fn contains(&self, devaddr: &u32) -> bool {
-        let addr_base = (BITS_23 as u32) & devaddr;
+        let addr_base = Self::addr_base(*devaddr);
         addr_base >= self.base && addr_base < (self.base + self.size)
     }
+
+    /// The part of a devaddr that is compared against the subnet range of a
+    /// filter
+    pub fn addr_base(devaddr: u32) -> u32 {
+        (BITS_23 as u32) & devaddr
+    }
 }
 
 #[cfg(test)]
@@ -94,6 +203,32 @@ mod tests {
             assert_eq!(8, filter.size);
             assert!(filter.contains(&2063));
         }
+
+        #[test]
+        fn to_bin() {
+            static MASK: [u8; 6] = [0, 4, 4, 127, 255, 254];
+            let filter = DevAddrFilter::new(2056, 8).expect("devaddr filter");
+            assert_eq!(MASK.to_vec(), filter.to_bin());
+            let filter = DevAddrFilter::from_bin(filter.to_bin());
+            assert_eq!((2056, 8), (filter.base(), filter.size()));
+            assert_eq!("0x000808/8", filter.to_string());
+            assert!(DevAddrFilter::new(2056, 12).is_err());
+            assert!(DevAddrFilter::new(2052, 8).is_err());
+        }
+
+        #[test]
+        fn deserialize() {
+            let filter: DevAddrFilter =
+                serde_json::from_str(r#"{"base": 2056, "size": 8}"#).expect("devaddr filter");
+            assert_eq!((2056, 8), (filter.base(), filter.size()));
+            // Subnets are validated like the ones constructed with new
+            for subnet in [
+                r#"{"base": 2056, "size": 2}"#,
+                r#"{"base": 4294967288, "size": 8}"#,
+            ] {
+                assert!(serde_json::from_str::<DevAddrFilter>(subnet).is_err());
+            }
+        }
     }
 
     mod eui {
@@ -144,6 +279,27 @@ mod tests {
             for [deveui, appeui] in SOME_KEYS.into_iter() {
                 assert!(filter.contains(&Eui { appeui, deveui }))
             }
+            assert_eq!(SOME_FILTER_BIN.to_vec(), filter.to_bin());
+        }
+
+        #[test]
+        fn from_euis() {
+            let euis: Vec<Eui> = (1..=10)
+                .map(|n| Eui {
+                    deveui: n,
+                    appeui: n * 100,
+                })
+                .collect();
+            let filter = EuiFilter::from_euis(&euis).expect("eui filter");
+            for eui in &euis {
+                assert!(filter.contains(eui));
+            }
+            assert!(EuiFilter::from_euis(&[]).is_none());
+
+            let filter = EuiFilter::from_bin(filter.to_bin());
+            assert!(filter.contains(&euis[0]));
+            let filter = EuiFilter::from_pairs(&[(1, 100)]).expect("eui filter");
+            assert!(filter.contains(&euis[0]));
         }
     }
 }
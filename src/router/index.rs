@@ -0,0 +1,178 @@
+use super::{filter::eui_hash, DevAddrFilter, EuiFilter, Routing};
+use helium_proto::{routing_information::Data as RoutingData, RoutingInformation};
+use lorawan::subnet;
+
+/// An index over the routing of a set of routers, keyed by `K`.
+///
+/// Matching a packet against the index hashes an EUI only once for all EUI
+/// filters, and looks up devaddrs in subnets sorted by their start address
+/// rather than checking every subnet of every router. The index is immutable
+/// and is expected to be rebuilt when the routing of any router changes.
+#[derive(Debug)]
+pub struct RoutingIndex<K> {
+    keys: Vec<K>,
+    filters: Vec<(EuiFilter, usize)>,
+    /// Subnets sorted by start address
+    subnets: Vec<Subnet>,
+    /// The largest end address of the subnets up to and including the subnet
+    /// at the same position. Used to stop searching overlapping subnets.
+    subnet_max_ends: Vec<u32>,
+    /// NetIDs sorted by netid
+    netids: Vec<(u32, usize)>,
+}
+
+#[derive(Debug)]
+struct Subnet {
+    start: u32,
+    end: u32,
+    key: usize,
+}
+
+impl<K> Default for RoutingIndex<K> {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            filters: vec![],
+            subnets: vec![],
+            subnet_max_ends: vec![],
+            netids: vec![],
+        }
+    }
+}
+
+impl<K> RoutingIndex<K> {
+    pub fn new<'a, I>(routings: I) -> Self
+    where
+        I: IntoIterator<Item = (K, &'a Routing)>,
+    {
+        let mut index = Self::default();
+        for (key, routing) in routings {
+            let key_index = index.keys.len();
+            index.keys.push(key);
+            index.filters.extend(
+                routing
+                    .filters()
+                    .iter()
+                    .map(|filter| (filter.clone(), key_index)),
+            );
+            index
+                .subnets
+                .extend(routing.subnets().iter().map(|subnet| Subnet {
+                    start: subnet.base(),
+                    end: subnet.base() + subnet.size(),
+                    key: key_index,
+                }));
+            index
+                .netids
+                .extend(routing.netids().iter().map(|netid| (*netid, key_index)));
+        }
+        index.subnets.sort_unstable_by_key(|subnet| subnet.start);
+        index.subnet_max_ends = index
+            .subnets
+            .iter()
+            .scan(0, |max_end, subnet| {
+                *max_end = subnet.end.max(*max_end);
+                Some(*max_end)
+            })
+            .collect();
+        index.netids.sort_unstable();
+        index
+    }
+
+    /// Returns the keys of all routers whose routing matches the given
+    /// routing information. Each key is returned at most once.
+    pub fn matches(&self, routing_info: &Option<RoutingInformation>) -> Vec<&K> {
+        let mut matches = match routing_info.as_ref().and_then(|info| info.data.as_ref()) {
+            Some(RoutingData::Eui(eui)) => {
+                let hash = eui_hash(eui);
+                self.filters
+                    .iter()
+                    .filter(|(filter, _)| filter.contains_hash(hash))
+                    .map(|(_, key)| *key)
+                    .collect()
+            }
+            Some(RoutingData::Devaddr(devaddr)) => {
+                let mut matches = self.subnet_matches(DevAddrFilter::addr_base(*devaddr));
+                if !self.netids.is_empty() {
+                    matches.extend(self.netid_matches(subnet::netid_from_devaddr(*devaddr)));
+                }
+                matches
+            }
+            None => vec![],
+        };
+        matches.sort_unstable();
+        matches.dedup();
+        matches.into_iter().map(|key| &self.keys[key]).collect()
+    }
+
+    fn subnet_matches(&self, addr: u32) -> Vec<usize> {
+        let end = self.subnets.partition_point(|subnet| subnet.start <= addr);
+        let mut matches = vec![];
+        for position in (0..end).rev() {
+            if self.subnet_max_ends[position] <= addr {
+                break;
+            }
+            let subnet = &self.subnets[position];
+            if addr < subnet.end {
+                matches.push(subnet.key);
+            }
+        }
+        matches
+    }
+
+    fn netid_matches(&self, netid: u32) -> impl Iterator<Item = usize> + '_ {
+        let start = self.netids.partition_point(|(entry, _)| *entry < netid);
+        self.netids[start..]
+            .iter()
+            .take_while(move |(entry, _)| *entry == netid)
+            .map(|(_, key)| *key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helium_proto::Eui;
+
+    fn devaddr_info(devaddr: u32) -> Option<RoutingInformation> {
+        Some(RoutingInformation {
+            data: Some(RoutingData::Devaddr(devaddr)),
+        })
+    }
+
+    #[test]
+    fn matches_like_scan() {
+        let routings: Vec<Routing> = (0..20u32)
+            .map(|n| {
+                let subnets = vec![
+                    DevAddrFilter::new(n * 64, 64).expect("subnet"),
+                    // Overlaps with the subnets of the other routings
+                    DevAddrFilter::new((n % 4) * 256, 256).expect("subnet"),
+                ];
+                let filters = EuiFilter::from_pairs(&[(n as u64, 100)])
+                    .into_iter()
+                    .collect();
+                Routing::from_filters(n, filters, subnets)
+            })
+            .collect();
+        let index = RoutingIndex::new(routings.iter().map(|routing| (routing.oui, routing)));
+        for devaddr in (0..2048).chain([0x48000010, 0xFFFFFFFF]) {
+            let info = devaddr_info(devaddr);
+            let expected: Vec<u32> = routings
+                .iter()
+                .filter(|routing| routing.matches_routing_info(&info))
+                .map(|routing| routing.oui)
+                .collect();
+            let actual: Vec<u32> = index.matches(&info).into_iter().copied().collect();
+            assert_eq!(expected, actual, "devaddr {devaddr}");
+        }
+        let info = Some(RoutingInformation {
+            data: Some(RoutingData::Eui(Eui {
+                deveui: 3,
+                appeui: 100,
+            })),
+        });
+        assert_eq!(vec![&3], index.matches(&info));
+        assert!(index.matches(&None).is_empty());
+    }
+}
@@ -1,11 +1,17 @@
+pub mod accounting;
+pub mod arbiter;
+pub mod chain_vars;
 pub mod client;
 pub mod dispatcher;
 pub mod filter;
+pub mod index;
 pub mod routing;
+pub mod seeds;
 pub mod store;
 
 pub use client::RouterClient;
 pub use dispatcher::Dispatcher;
 pub use filter::{DevAddrFilter, EuiFilter};
+pub use index::RoutingIndex;
 pub use routing::Routing;
 pub use store::{QuePacket, RouterStore};
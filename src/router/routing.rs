@@ -1,6 +1,7 @@
 use super::{DevAddrFilter, EuiFilter};
-use crate::{KeyedUri, PublicKey, Result};
-use helium_proto::{routing_information::Data as RoutingData, RoutingInformation};
+use crate::{settings::StaticRouteSettings, KeyedUri, PublicKey, Result};
+use helium_proto::{routing_information::Data as RoutingData, Eui, RoutingInformation};
+use lorawan::subnet;
 use slog::{warn, Logger};
 use std::{convert::TryFrom, sync::Arc};
 
@@ -10,6 +11,7 @@ pub struct Routing {
     pub(crate) uris: Vec<KeyedUri>,
     filters: Vec<EuiFilter>,
     subnets: Vec<DevAddrFilter>,
+    netids: Vec<u32>,
 }
 
 impl Routing {
@@ -17,6 +19,28 @@ impl Routing {
         self.uris.iter().any(|keyed_uri| keyed_uri == uri)
     }
 
+    /// The number of EUI filters in this routing
+    pub fn filters_len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// The number of devaddr subnets in this routing
+    pub fn subnets_len(&self) -> usize {
+        self.subnets.len()
+    }
+
+    pub fn filters(&self) -> &[EuiFilter] {
+        &self.filters
+    }
+
+    pub fn subnets(&self) -> &[DevAddrFilter] {
+        &self.subnets
+    }
+
+    pub fn netids(&self) -> &[u32] {
+        &self.netids
+    }
+
     pub fn matches_routing_info(&self, routing_info: &Option<RoutingInformation>) -> bool {
         match routing_info {
             Some(RoutingInformation { ref data }) => self.matches_routing_data(data),
@@ -30,6 +54,8 @@ impl Routing {
             Some(RoutingData::Eui(eui)) => self.filters.iter().any(|filter| filter.contains(eui)),
             Some(RoutingData::Devaddr(dev_addr)) => {
                 self.subnets.iter().any(|filter| filter.contains(dev_addr))
+                    || (!self.netids.is_empty()
+                        && self.netids.contains(&subnet::netid_from_devaddr(*dev_addr)))
             }
         }
     }
@@ -77,6 +103,50 @@ impl Routing {
             filters,
             subnets,
             uris,
+            netids: vec![],
         })
     }
+
+    /// Constructs a routing entry that does not match any packets but
+    /// delivers to the given routers. This is used for default routers which
+    /// receive packets that no other routing entry matched.
+    pub fn from_uris(oui: u32, uris: Vec<KeyedUri>) -> Self {
+        Self {
+            oui,
+            uris,
+            filters: vec![],
+            subnets: vec![],
+            netids: vec![],
+        }
+    }
+
+    /// Constructs a routing for the given EUI filters and devaddr subnets
+    /// without router uris
+    pub fn from_filters(oui: u32, filters: Vec<EuiFilter>, subnets: Vec<DevAddrFilter>) -> Self {
+        Self {
+            oui,
+            uris: vec![],
+            filters,
+            subnets,
+            netids: vec![],
+        }
+    }
+
+    pub fn from_settings(settings: &StaticRouteSettings) -> Self {
+        let euis: Vec<Eui> = settings
+            .euis
+            .iter()
+            .map(|eui| Eui {
+                deveui: eui.dev_eui,
+                appeui: eui.app_eui,
+            })
+            .collect();
+        Self {
+            oui: settings.oui,
+            filters: EuiFilter::from_euis(&euis).into_iter().collect(),
+            subnets: settings.devaddrs.clone(),
+            uris: settings.routers.clone(),
+            netids: settings.netids.clone(),
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use crate::{KeyedUri, PublicKey, Result};
+use http::Uri;
+use serde::{Deserialize, Serialize};
+use slog::{info, warn, Logger};
+use std::{fs, path::PathBuf, str::FromStr, sync::Arc};
+
+/// Maximum number of seed validators to persist
+pub const MAX_SEEDS: usize = 10;
+
+/// The persisted form of a seed validator
+#[derive(Debug, Serialize, Deserialize)]
+struct SeedEntry {
+    pubkey: String,
+    uri: String,
+}
+
+impl TryFrom<&SeedEntry> for KeyedUri {
+    type Error = crate::Error;
+    fn try_from(entry: &SeedEntry) -> Result<Self> {
+        Ok(Self {
+            uri: Uri::from_str(&entry.uri)?,
+            pubkey: Arc::new(PublicKey::from_str(&entry.pubkey)?),
+        })
+    }
+}
+
+impl From<&KeyedUri> for SeedEntry {
+    fn from(uri: &KeyedUri) -> Self {
+        Self {
+            pubkey: uri.pubkey.to_string(),
+            uri: uri.uri.to_string(),
+        }
+    }
+}
+
+/// Persists verified validators to use as seeds across restarts, so a
+/// gateway can bootstrap after the configured seed validators are retired.
+#[derive(Debug)]
+pub struct SeedStore {
+    path: Option<PathBuf>,
+}
+
+impl SeedStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Loads the persisted seeds. Returns None when there are no persisted
+    /// seeds or the persisted file is unusable.
+    pub fn load(&self, logger: &Logger) -> Option<Vec<KeyedUri>> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return None,
+        };
+        let entries: Result<Vec<SeedEntry>> = fs::read(path)
+            .map_err(Into::into)
+            .and_then(|data| serde_json::from_slice(&data).map_err(Into::into));
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!(logger, "ignoring invalid seed file: {err:?}");
+                return None;
+            }
+        };
+        // Skip invalid entries rather than discarding the whole list
+        let seeds: Vec<KeyedUri> = entries
+            .iter()
+            .filter_map(|entry| KeyedUri::try_from(entry).ok())
+            .collect();
+        if seeds.is_empty() {
+            warn!(logger, "ignoring seed file without valid seeds");
+            return None;
+        }
+        info!(logger, "loaded seed gateways";
+            "path" => path.to_string_lossy().to_string(),
+            "seeds" => seeds.len());
+        Some(seeds)
+    }
+
+    pub fn save(&self, seeds: &[KeyedUri], logger: &Logger) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let entries: Vec<SeedEntry> = seeds.iter().map(SeedEntry::from).collect();
+        let result: Result = serde_json::to_vec(&entries)
+            .map_err(Into::into)
+            .and_then(|data| fs::write(path, data).map_err(Into::into));
+        if let Err(err) = result {
+            warn!(logger, "failed to save seed gateways: {err:?}");
+        }
+    }
+}
+
+/// Merges freshly verified validators with the current seeds, keeping the
+/// verified ones first, up to `MAX_SEEDS` seeds.
+pub fn merge(verified: Vec<KeyedUri>, current: &[KeyedUri]) -> Vec<KeyedUri> {
+    let mut seeds = verified;
+    for seed in current {
+        if !seeds.contains(seed) {
+            seeds.push(seed.clone());
+        }
+    }
+    seeds.truncate(MAX_SEEDS);
+    seeds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(pubkey: &str, host: u8) -> KeyedUri {
+        KeyedUri::try_from(&SeedEntry {
+            pubkey: pubkey.to_string(),
+            uri: format!("http://10.0.0.{host}:8080"),
+        })
+        .expect("seed")
+    }
+
+    #[test]
+    fn merge_verified_first() {
+        let pubkey = "11tk4zzbyfMPYYHYda255ACoqfYFVdrUSoCWrCYfn8BoyuYrERK";
+        let current: Vec<KeyedUri> = (0..MAX_SEEDS as u8).map(|n| seed(pubkey, n)).collect();
+        let seeds = merge(vec![seed(pubkey, 100), seed(pubkey, 3)], &current);
+        assert_eq!(MAX_SEEDS, seeds.len());
+        assert_eq!(seed(pubkey, 100), seeds[0]);
+        assert_eq!(seed(pubkey, 3), seeds[1]);
+        assert_eq!(seed(pubkey, 0), seeds[2]);
+
+        let entry = SeedEntry::from(&seeds[0]);
+        assert_eq!(seeds[0], KeyedUri::try_from(&entry).expect("seed"));
+    }
+}
@@ -14,6 +14,9 @@ pub struct RouterStore {
 pub struct QuePacket {
     received: Instant,
     packet: Packet,
+    /// Whether the packet was also sent to routers of other OUIs, so its
+    /// downlink needs arbitration
+    arbitrated: bool,
 }
 
 impl QuePacket {
@@ -24,6 +27,10 @@ impl QuePacket {
     pub fn packet(&self) -> &Packet {
         &self.packet
     }
+
+    pub fn arbitrated(&self) -> bool {
+        self.arbitrated
+    }
 }
 
 impl Deref for QuePacket {
@@ -44,15 +51,32 @@ impl RouterStore {
         }
     }
 
-    pub fn store_waiting_packet(&mut self, packet: Packet, received: Instant) -> Result {
-        self.waiting_packets
-            .push_back(QuePacket { packet, received });
+    pub fn store_waiting_packet(
+        &mut self,
+        packet: Packet,
+        received: Instant,
+        arbitrated: bool,
+    ) -> Result {
+        self.waiting_packets.push_back(QuePacket {
+            packet,
+            received,
+            arbitrated,
+        });
         if self.waiting_packets_len() > self.max_packets as usize {
             self.waiting_packets.pop_front();
         }
         Ok(())
     }
 
+    /// Changes the maximum number of waiting packets, dropping the oldest
+    /// packets that no longer fit.
+    pub fn set_max_packets(&mut self, max_packets: u16) {
+        self.max_packets = max_packets;
+        while self.waiting_packets_len() > self.max_packets as usize {
+            self.waiting_packets.pop_front();
+        }
+    }
+
     pub fn pop_waiting_packet(&mut self) -> Option<QuePacket> {
         self.waiting_packets.pop_front()
     }
@@ -1,23 +1,90 @@
 use crate::{
     api::LocalServer,
     beaconer, gateway,
-    router::{dispatcher, Dispatcher},
+    mirror::{self, Mirror},
+    reload::{self, Reloader},
+    roaming::{self, Roamer},
+    router::{
+        arbiter::{self, Arbiter},
+        dispatcher, Dispatcher,
+    },
     settings::{self, Settings},
-    updater::Updater,
+    status::StatusReceivers,
+    updater::{self, Updater},
     Result,
 };
 use slog::{info, Logger};
+use std::path::Path;
 
-pub async fn run(shutdown: &triggered::Listener, settings: &Settings, logger: &Logger) -> Result {
+pub async fn run(
+    shutdown: &triggered::Listener,
+    config: &Path,
+    settings: &Settings,
+    logger: &Logger,
+) -> Result {
     let (gateway_tx, gateway_rx) = gateway::message_channel(10);
     let (dispatcher_tx, dispatcher_rx) = dispatcher::message_channel(20);
     let (beaconing_tx, beaconing_rx) = beaconer::message_channel(10);
-    let mut beaconer = beaconer::Beaconer::new(settings, gateway_tx.clone(), beaconing_rx);
-    let mut dispatcher = Dispatcher::new(dispatcher_rx, gateway_tx, settings)?;
-    let mut gateway =
-        gateway::Gateway::new(dispatcher_tx.clone(), gateway_rx, beaconing_tx, settings).await?;
-    let updater = Updater::new(settings)?;
-    let api = LocalServer::new(dispatcher_tx, settings)?;
+    let (mirror_tx, mirror_rx) = mirror::message_channel(50);
+    let (roaming_tx, roaming_rx) = roaming::message_channel(settings, 20);
+    let (arbiter_tx, arbiter_rx) = arbiter::message_channel(20);
+    let (updater_tx, updater_rx) = updater::message_channel(1);
+    let (reload_tx, reload_rx) = reload::message_channel(1);
+    let mut beaconer = beaconer::Beaconer::new(
+        settings,
+        gateway_tx.clone(),
+        mirror_tx.clone(),
+        beaconing_rx,
+    )?;
+    let mut mirror = Mirror::new(settings, gateway_tx.clone(), mirror_rx, shutdown).await?;
+    let mut roamer = Roamer::new(settings, gateway_tx.clone(), roaming_rx, roaming_tx.clone());
+    let mut arbiter = Arbiter::new(
+        settings,
+        arbiter_rx,
+        arbiter_tx.clone(),
+        gateway_tx.clone(),
+        mirror_tx.clone(),
+    );
+    let mut dispatcher = Dispatcher::new(
+        dispatcher_rx,
+        dispatcher_tx.clone(),
+        gateway_tx.clone(),
+        mirror_tx.clone(),
+        roaming_tx,
+        arbiter_tx,
+        settings,
+    )?;
+    let mut gateway = gateway::Gateway::new(
+        dispatcher_tx.clone(),
+        gateway_rx,
+        beaconing_tx.clone(),
+        mirror_tx,
+        settings,
+    )
+    .await?;
+    let mut updater = Updater::new(settings, updater_rx)?;
+    let mut reloader = Reloader::new(
+        config,
+        settings,
+        reload_rx,
+        dispatcher_tx.clone(),
+        beaconing_tx,
+        updater_tx,
+    );
+    let status = StatusReceivers {
+        gateway: gateway.status(),
+        dispatcher: dispatcher.status(),
+        beaconer: beaconer.status(),
+        updater: updater.status(),
+    };
+    let api = LocalServer::new(
+        dispatcher_tx,
+        gateway_tx,
+        reload_tx,
+        status,
+        mirror.tap(),
+        settings,
+    )?;
     info!(logger,
         "starting server";
         "version" => settings::version().to_string(),
@@ -27,7 +94,11 @@ pub async fn run(shutdown: &triggered::Listener, settings: &Settings, logger: &L
         beaconer.run(shutdown.clone(), logger),
         gateway.run(shutdown.clone(), logger),
         dispatcher.run(shutdown.clone(), logger),
+        mirror.run(shutdown.clone(), logger),
+        roamer.run(shutdown.clone(), logger),
+        arbiter.run(shutdown.clone(), logger),
         updater.run(shutdown.clone(), logger),
+        reloader.run(shutdown.clone(), logger),
         api.run(shutdown.clone(), logger),
     )
     .map(|_| ())
@@ -1,21 +1,23 @@
-use crate::{curl, Result};
+use crate::{curl, settings::ProxySettings, Result};
 use beacon::Entropy;
 use http::Uri;
 
 #[derive(Debug)]
 pub struct EntropyService {
     uri: Uri,
+    proxy: Option<ProxySettings>,
 }
 
 impl EntropyService {
-    pub fn new(uri: Uri) -> Self {
-        Self { uri }
+    pub fn new(uri: Uri, proxy: Option<ProxySettings>) -> Self {
+        Self { uri, proxy }
     }
 
     pub async fn get_entropy(&self) -> Result<Entropy> {
         curl::get(
             self.uri.to_string(),
             ["-H", "Accept: application/json"],
+            self.proxy.as_ref(),
             move |output| {
                 let entropy: Entropy = serde_json::from_slice(output)?;
                 Ok(entropy)
@@ -1,10 +1,10 @@
 use crate::{
-    service::{CONNECT_TIMEOUT, RPC_TIMEOUT},
+    service::{self, ConnectSettings},
     Error, KeyedUri, Keypair, MsgSign, MsgVerify, PublicKey, Region, RegionParams, Result,
 };
 use helium_proto::{
     gateway_resp_v1,
-    services::{self, Channel, Endpoint},
+    services::{self, Channel},
     BlockchainVarV1, GatewayConfigReqV1, GatewayConfigRespV1, GatewayRegionParamsReqV1,
     GatewayRegionParamsUpdateReqV1, GatewayRespV1, GatewayRoutingReqV1, GatewayScIsActiveReqV1,
     GatewayScIsActiveRespV1, GatewayValidatorsReqV1, GatewayValidatorsRespV1, GatewayVersionReqV1,
@@ -15,6 +15,7 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tokio_stream::Stream;
 
@@ -77,6 +78,52 @@ impl Response for GatewayRespV1 {
     }
 }
 
+/// The result of probing a validator for its responsiveness and chain state
+#[derive(Debug, Clone)]
+pub struct GatewayProbe {
+    /// Round trip time of a height request
+    pub rtt: Duration,
+    pub height: u64,
+    pub block_age: u64,
+    pub version: u64,
+}
+
+/// Score penalty, in milliseconds of round trip time, for each block a
+/// validator is behind the highest probed validator
+const PROBE_BLOCK_LAG_PENALTY: u64 = 1000;
+/// Score penalty, in milliseconds of round trip time, for a validator running
+/// an older version than the newest probed validator
+const PROBE_VERSION_PENALTY: u64 = 500;
+
+impl GatewayProbe {
+    /// Scores the probe relative to the best height and version of all probed
+    /// validators. Lower scores are better.
+    pub fn score(&self, best_height: u64, best_version: u64) -> u64 {
+        let lag = best_height.saturating_sub(self.height);
+        let version_penalty = if self.version < best_version {
+            PROBE_VERSION_PENALTY
+        } else {
+            0
+        };
+        self.rtt.as_millis() as u64 + lag * PROBE_BLOCK_LAG_PENALTY + version_penalty
+    }
+
+    /// Ranks the given probed items best first. Items with a block age over
+    /// the given maximum are dropped.
+    pub fn rank<T>(probes: Vec<(T, GatewayProbe)>, max_block_age: u64) -> Vec<T> {
+        let mut probes: Vec<(T, GatewayProbe)> = probes
+            .into_iter()
+            .filter(|(_, probe)| probe.block_age <= max_block_age)
+            .collect();
+        let best_height = probes.iter().map(|(_, probe)| probe.height).max();
+        let best_version = probes.iter().map(|(_, probe)| probe.version).max();
+        if let (Some(best_height), Some(best_version)) = (best_height, best_version) {
+            probes.sort_by_key(|(_, probe)| probe.score(best_height, best_version));
+        }
+        probes.into_iter().map(|(item, _)| item).collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GatewayService {
     pub uri: KeyedUri,
@@ -84,40 +131,19 @@ pub struct GatewayService {
 }
 
 impl GatewayService {
-    pub fn new(keyed_uri: &KeyedUri) -> Result<Self> {
-        let channel = Endpoint::from(keyed_uri.uri.clone())
-            .connect_timeout(CONNECT_TIMEOUT)
-            .timeout(RPC_TIMEOUT)
-            .connect_lazy();
+    pub fn new(keyed_uri: &KeyedUri, connect: &ConnectSettings) -> Result<Self> {
+        let channel = service::channel(keyed_uri.uri.clone(), connect)?;
         Ok(Self {
             uri: keyed_uri.clone(),
             client: GatewayClient::new(channel),
         })
     }
 
-    pub fn select_seed(seed_uris: &[KeyedUri]) -> Result<Self> {
+    pub fn select_seed(seed_uris: &[KeyedUri], connect: &ConnectSettings) -> Result<Self> {
         seed_uris
             .choose(&mut OsRng)
             .ok_or_else(|| Error::custom("empty uri list"))
-            .and_then(Self::new)
-    }
-
-    pub async fn random_new(
-        &mut self,
-        fetch_count: u8,
-        cancel: triggered::Listener,
-    ) -> Result<Option<Self>> {
-        tokio::select! {
-            gateways = self.validators(fetch_count.into()) => match gateways {
-                Ok(gateways) => gateways
-                    .choose(&mut OsRng)
-                    .ok_or_else(|| Error::custom("empty gateway list"))
-                    .and_then(Self::new)
-                    .map(Some),
-                Err(err) => Err(err)
-            },
-            _ = cancel.clone() => Ok(None)
-        }
+            .and_then(|uri| Self::new(uri, connect))
     }
 
     pub async fn routing(&mut self, height: u64) -> Result<Streaming> {
@@ -200,9 +226,14 @@ impl GatewayService {
         Ok(resp)
     }
 
-    pub async fn config(&mut self, keys: Vec<String>) -> Result<Vec<BlockchainVarV1>> {
-        match self.get_config(keys).await?.msg {
-            Some(gateway_resp_v1::Msg::ConfigResp(GatewayConfigRespV1 { result })) => Ok(result),
+    /// Fetches the given chain variables along with the height they were
+    /// fetched at
+    pub async fn config(&mut self, keys: Vec<String>) -> Result<(Vec<BlockchainVarV1>, u64)> {
+        let resp = self.get_config(keys).await?;
+        match resp.msg {
+            Some(gateway_resp_v1::Msg::ConfigResp(GatewayConfigRespV1 { result })) => {
+                Ok((result, resp.height))
+            }
             Some(other) => Err(Error::custom(format!("invalid config response {other:?}"))),
             None => Err(Error::custom("empty config response")),
         }
@@ -213,6 +244,21 @@ impl GatewayService {
         Ok((resp.height, resp.block_age))
     }
 
+    /// Probes the validator for its round trip time, height, block age and
+    /// version.
+    pub async fn probe(&mut self) -> Result<GatewayProbe> {
+        let start = Instant::now();
+        let (height, block_age) = self.height().await?;
+        let rtt = start.elapsed();
+        let version = self.version().await?;
+        Ok(GatewayProbe {
+            rtt,
+            height,
+            block_age,
+            version,
+        })
+    }
+
     pub async fn validators(&mut self, quantity: u32) -> Result<Vec<KeyedUri>> {
         let resp = self
             .client
@@ -247,3 +293,36 @@ impl GatewayService {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn probe(rtt: u64, height: u64, block_age: u64, version: u64) -> GatewayProbe {
+        GatewayProbe {
+            rtt: Duration::from_millis(rtt),
+            height,
+            block_age,
+            version,
+        }
+    }
+
+    #[test]
+    fn rank() {
+        let probes = vec![
+            // Far away
+            ("far", probe(900, 100, 60, 2)),
+            // Close but lagging two blocks
+            ("lagging", probe(50, 98, 60, 2)),
+            // Close but on an older version
+            ("old", probe(100, 100, 60, 1)),
+            // Close but stalled
+            ("stalled", probe(10, 100, 3600, 2)),
+            ("best", probe(200, 100, 60, 2)),
+        ];
+        assert_eq!(
+            vec!["best", "old", "far", "lagging"],
+            GatewayProbe::rank(probes, 1800)
+        );
+    }
+}
@@ -1,4 +1,12 @@
-use std::time::Duration;
+use crate::{
+    proxy,
+    settings::{ProxySettings, TlsSettings},
+    Error, Result, Settings,
+};
+use helium_proto::services::{Channel, Endpoint};
+use http::{uri::Scheme, Uri};
+use std::{fs, io, time::Duration};
+use tonic::transport::{Certificate, ClientTlsConfig};
 
 pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 pub const RPC_TIMEOUT: Duration = Duration::from_secs(5);
@@ -8,3 +16,69 @@ pub mod gateway;
 pub mod poc;
 pub mod router;
 mod version;
+
+/// Settings for connections to gRPC services
+#[derive(Debug, Clone)]
+pub struct ConnectSettings {
+    pub tls: TlsSettings,
+    pub proxy: Option<ProxySettings>,
+}
+
+impl From<&Settings> for ConnectSettings {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            tls: settings.tls.clone(),
+            proxy: settings.proxy.clone(),
+        }
+    }
+}
+
+/// Constructs a lazily connecting channel with the default timeouts for the
+/// given uri. TLS is used for https uris and the connection goes through the
+/// configured proxy, if any.
+pub fn channel(uri: Uri, settings: &ConnectSettings) -> Result<Channel> {
+    let endpoint = endpoint(uri, &settings.tls)?;
+    let proxy = match &settings.proxy {
+        Some(proxy) => proxy.clone(),
+        None => return Ok(endpoint.connect_lazy()),
+    };
+    let connector = tower::service_fn(move |target: Uri| {
+        let proxy = proxy.clone();
+        async move {
+            proxy::connect(&proxy, &target)
+                .await
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{err:?}")))
+        }
+    });
+    Ok(endpoint.connect_with_connector_lazy(connector))
+}
+
+fn endpoint(uri: Uri, tls: &TlsSettings) -> Result<Endpoint> {
+    let endpoint = Endpoint::from(uri.clone())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(RPC_TIMEOUT);
+    if uri.scheme() != Some(&Scheme::HTTPS) {
+        return Ok(endpoint);
+    }
+    Ok(endpoint.tls_config(tls_config(&uri, tls)?)?)
+}
+
+fn tls_config(uri: &Uri, settings: &TlsSettings) -> Result<ClientTlsConfig> {
+    let mut pem = vec![];
+    if !settings.ca_bundle_only && settings.system_roots.exists() {
+        pem.extend(fs::read(&settings.system_roots)?);
+        pem.push(b'\n');
+    }
+    if let Some(ca_bundle) = &settings.ca_bundle {
+        pem.extend(fs::read(ca_bundle)?);
+    }
+    if pem.is_empty() {
+        return Err(Error::custom(format!("no trusted certificates for {uri}")));
+    }
+    let domain = uri
+        .host()
+        .ok_or_else(|| Error::custom(format!("missing host in {uri}")))?;
+    Ok(ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(pem))
+        .domain_name(domain))
+}
@@ -1,11 +1,11 @@
 use crate::{
-    service::{CONNECT_TIMEOUT, RPC_TIMEOUT},
+    service::{self, ConnectSettings},
     Keypair, MsgSign, Result,
 };
 use helium_proto::services::{
     self,
     poc_lora::{LoraBeaconReportReqV1, LoraWitnessReportReqV1},
-    Channel, Endpoint,
+    Channel,
 };
 use http::Uri;
 use std::sync::Arc;
@@ -16,13 +16,10 @@ type PocLoraClient = services::poc_lora::Client<Channel>;
 pub struct PocLoraService(PocLoraClient);
 
 impl PocLoraService {
-    pub fn new(uri: Uri) -> Self {
-        let channel = Endpoint::from(uri)
-            .connect_timeout(CONNECT_TIMEOUT)
-            .timeout(RPC_TIMEOUT)
-            .connect_lazy();
+    pub fn new(uri: Uri, connect: &ConnectSettings) -> Result<Self> {
+        let channel = service::channel(uri, connect)?;
         let client = services::poc_lora::Client::new(channel);
-        Self(client)
+        Ok(Self(client))
     }
 
     pub async fn submit_beacon(
@@ -1,9 +1,9 @@
 use crate::{
-    service::{CONNECT_TIMEOUT, RPC_TIMEOUT},
+    service::{self, ConnectSettings},
     KeyedUri, Result,
 };
 use helium_proto::{
-    services::{self, Channel, Endpoint},
+    services::{self, Channel},
     BlockchainStateChannelMessageV1,
 };
 
@@ -16,11 +16,8 @@ pub struct RouterService {
 }
 
 impl RouterService {
-    pub fn new(keyed_uri: KeyedUri) -> Result<Self> {
-        let router_channel = Endpoint::from(keyed_uri.uri.clone())
-            .timeout(RPC_TIMEOUT)
-            .connect_timeout(CONNECT_TIMEOUT)
-            .connect_lazy();
+    pub fn new(keyed_uri: KeyedUri, connect: &ConnectSettings) -> Result<Self> {
+        let router_channel = service::channel(keyed_uri.uri.clone(), connect)?;
         Ok(Self {
             uri: keyed_uri,
             router_client: RouterClient::new(router_channel),
@@ -1,18 +1,24 @@
 use crate::{
-    api::GatewayStakingMode, releases, Error, KeyedUri, Keypair, PublicKey, Region, Result,
+    api::GatewayStakingMode, releases, router::DevAddrFilter, Error, KeyedUri, Keypair, PublicKey,
+    Region, Result,
 };
 use config::{Config, Environment, File};
 use http::uri::Uri;
 pub use log_method::LogMethod;
 use serde::Deserialize;
-use std::{fmt, path::Path, str::FromStr, sync::Arc};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
 pub fn version() -> semver::Version {
     semver::Version::parse(env!("CARGO_PKG_VERSION")).expect("unable to parse version")
 }
 
 /// Settings are all the configuration parameters the service needs to operate.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     /// The listen address to use for listening for the semtech UDP packet forwarder.
     /// Default "127.0.0.1:1680"
@@ -22,6 +28,15 @@ pub struct Settings {
     /// Default 4467
     #[serde(default = "default_api")]
     pub api: u16,
+    /// Serve the grpc API on a unix socket instead of the network port.
+    /// Defaults to none.
+    pub api_socket: Option<ApiSocketSettings>,
+    /// Policy for the sign and ecdh requests of the local API
+    pub signing: SigningSettings,
+    /// The listening network port for the HTTP/JSON API on localhost.
+    /// Defaults to none, which disables it. The HTTP API is not served when
+    /// the grpc API is restricted to a unix socket.
+    pub http_api: Option<u16>,
     /// The location of the keypair binary file for the gateway. If the keyfile
     /// is not found there a new one is generated and saved in that location.
     pub keypair: Arc<Keypair>,
@@ -32,6 +47,9 @@ pub struct Settings {
     /// The lorawan region to use. This value should line up with the configured
     /// region of the semtech packet forwarder. Defaults to "US915"
     pub region: Region,
+    /// Local region params to use before, or instead of, the region params
+    /// from a validator. Defaults to none.
+    pub region_params: Option<RegionParamsSettings>,
     /// Log settings
     pub log: LogSettings,
     /// Update settings
@@ -39,17 +57,47 @@ pub struct Settings {
     /// The routers to deliver packets to when no routers are found while
     /// processing a packet.
     pub routers: Option<Vec<KeyedUri>>,
+    /// How to select default routers for a packet (all, first_healthy or
+    /// round_robin). Defaults to all.
+    #[serde(default)]
+    pub default_router_policy: DefaultRouterPolicy,
     /// The validator(s) to query for chain related state. Defaults to a Helium
-    /// validator.
+    /// validator. When empty the gateway runs without a validator and only
+    /// uses the configured static routes.
+    #[serde(default)]
     pub gateways: Vec<KeyedUri>,
+    /// Persisted seed validators
+    pub seeds: SeedSettings,
+    /// The policy to select the downlink to transmit when the routers of
+    /// several OUIs answer the same uplink. Defaults to the first responder.
+    #[serde(default)]
+    pub downlink_policy: DownlinkPolicy,
+    /// Routes to deliver packets to independent of the routing information
+    /// received from a validator. Defaults to none.
+    #[serde(default)]
+    pub static_routes: Vec<StaticRouteSettings>,
+    /// TLS settings for validator, router and PoC ingest connections with an
+    /// https uri
+    pub tls: TlsSettings,
+    /// Outbound proxy for validator, router, PoC, entropy, roaming and update
+    /// traffic. Defaults to none.
+    pub proxy: Option<ProxySettings>,
     /// Cache settings
     pub cache: CacheSettings,
+    /// Per-OUI traffic accounting and limits
+    pub accounting: AccountingSettings,
     /// Proof-of-coverage (PoC) settings.
     pub poc: PocSettings,
+    /// Local sinks to mirror uplinks and downlinks to. Defaults to none.
+    #[serde(default)]
+    pub mirrors: Vec<MirrorSettings>,
+    /// Passive roaming settings for packets of devices on other LoRaWAN
+    /// networks. Defaults to no roaming.
+    pub roaming: Option<RoamingSettings>,
 }
 
 /// Settings for log method and level to be used by the running service.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct LogSettings {
     /// Log level to show (default info)
     pub level: log_level::Level,
@@ -62,7 +110,7 @@ pub struct LogSettings {
 }
 
 /// Settings for log method and level to be used by the running service.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct UpdateSettings {
     /// Whether the auto-update system is enabled (default: true)
     pub enabled: bool,
@@ -82,6 +130,111 @@ pub struct UpdateSettings {
     pub command: String,
 }
 
+/// Settings for persisting verified validators to use as seeds instead of the
+/// configured gateways
+#[derive(Debug, Deserialize, Clone)]
+pub struct SeedSettings {
+    /// File to persist the seed validators in. Defaults to none, which only
+    /// uses the configured gateways as seeds.
+    pub path: Option<PathBuf>,
+    /// Interval in seconds to refresh the persisted seed validators at while
+    /// connected to a validator
+    pub refresh_interval: u64,
+}
+
+/// Settings for serving the local API on a unix socket, which limits access
+/// to it by file ownership and permissions
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiSocketSettings {
+    /// The socket path. An existing socket at the path is replaced.
+    pub path: PathBuf,
+    /// The user id to own the socket. Defaults to the user of the service.
+    pub uid: Option<u32>,
+    /// The group id to own the socket. Defaults to the group of the service.
+    pub gid: Option<u32>,
+    /// The permissions of the socket (default 0o600)
+    #[serde(default = "default_api_socket_mode")]
+    pub mode: u32,
+}
+
+/// Policy for the local API requests that use the gateway key
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningSettings {
+    /// Whether sign requests are served (default true)
+    pub sign: bool,
+    /// Whether ecdh requests are served (default true)
+    pub ecdh: bool,
+    /// The message types that sign requests are limited to. Defaults to
+    /// none, which signs any data.
+    #[serde(default)]
+    pub messages: Vec<SignedMessage>,
+    /// The clients allowed to make sign and ecdh requests. Defaults to none,
+    /// which allows any client.
+    #[serde(default)]
+    pub clients: Vec<ApiClientSettings>,
+}
+
+/// A message type that may be signed through the local API. Messages are
+/// only signed when they decode as the given type, name this gateway and are
+/// not signed yet.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignedMessage {
+    /// An add gateway transaction
+    AddGateway,
+    /// An assert location (v2) transaction
+    AssertLocation,
+    /// A PoC beacon report
+    Beacon,
+    /// A PoC witness report
+    Witness,
+}
+
+/// A local API client, which passes its token as "authorization: Bearer
+/// <token>" request metadata
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiClientSettings {
+    /// Name of the client, used in logs
+    pub name: String,
+    pub token: String,
+}
+
+/// Settings for a local region params file
+#[derive(Debug, Deserialize, Clone)]
+pub struct RegionParamsSettings {
+    /// TOML or JSON channel plan file with the antenna gain in dBi and the
+    /// frequency, bandwidth, max EIRP and spreading factors of each channel
+    pub path: PathBuf,
+    /// Whether region params received from a validator replace the local
+    /// ones (default true)
+    #[serde(default = "default_validator_override")]
+    pub validator_override: bool,
+}
+
+/// Settings for TLS connections to gRPC services
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSettings {
+    /// PEM file with the CA certificates of the system. Ignored when missing.
+    pub system_roots: PathBuf,
+    /// PEM file with CA certificates to trust in addition to the system
+    /// certificates. Defaults to none.
+    pub ca_bundle: Option<PathBuf>,
+    /// Whether to only trust the CA certificates in the bundle and ignore the
+    /// system certificates (default false). Server certificates are still
+    /// verified against these CAs, individual server keys are not pinned.
+    #[serde(default)]
+    pub ca_bundle_only: bool,
+}
+
+/// Settings for an outbound proxy
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxySettings {
+    /// The proxy uri, "http://host:port" for an HTTP CONNECT proxy or
+    /// "socks5://host:port" for a SOCKS5 proxy without authentication
+    #[serde(with = "http_serde::uri")]
+    pub uri: Uri,
+}
+
 /// Settings for cache storage
 #[derive(Debug, Deserialize, Clone)]
 pub struct CacheSettings {
@@ -89,6 +242,168 @@ pub struct CacheSettings {
     pub max_packets: u16,
 }
 
+/// Settings for per-OUI and per-router traffic accounting
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccountingSettings {
+    /// File to persist the traffic counters in across restarts. Defaults to
+    /// none, which keeps counters in memory only.
+    pub path: Option<PathBuf>,
+    /// Interval in seconds to persist the traffic counters at
+    pub save_interval: u64,
+    /// Limits on the traffic sent to the routers of an OUI. Defaults to none.
+    #[serde(default)]
+    pub limits: Vec<OuiLimitSettings>,
+}
+
+/// A limit on the uplinks sent to the routers of an OUI. Uplinks over a limit
+/// are not sent to the OUI and are counted as limited.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OuiLimitSettings {
+    pub oui: u32,
+    /// Maximum number of uplinks to send per minute
+    pub uplinks_per_minute: Option<u32>,
+    /// Maximum total data credits to spend on the OUI as counted by the
+    /// persisted counters
+    pub dc_budget: Option<u64>,
+}
+
+/// The policy used to select which default routers receive packets that did
+/// not match any other route.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultRouterPolicy {
+    /// Send to every default router
+    All,
+    /// Send to the first default router, in configured order, that is healthy
+    FirstHealthy,
+    /// Rotate through the default routers for each packet
+    RoundRobin,
+}
+
+impl Default for DefaultRouterPolicy {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// Settings for a statically configured route.
+#[derive(Debug, Deserialize, Clone)]
+pub struct StaticRouteSettings {
+    /// The OUI the route is registered as. OUI 0 is reserved for the default
+    /// routers.
+    #[serde(with = "static_route_oui")]
+    pub oui: u32,
+    /// Devaddr subnets, as base and size, routed to the routers
+    #[serde(default)]
+    pub devaddrs: Vec<DevAddrFilter>,
+    /// NetIDs for which all devaddrs are routed to the routers
+    #[serde(default)]
+    pub netids: Vec<u32>,
+    /// Device and app EUIs for which join requests are routed to the routers
+    #[serde(default)]
+    pub euis: Vec<EuiSettings>,
+    /// The routers to deliver packets for this route to
+    pub routers: Vec<KeyedUri>,
+}
+
+/// A device and app EUI pair. EUIs are specified as hex strings.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct EuiSettings {
+    #[serde(with = "hex_eui")]
+    pub dev_eui: u64,
+    #[serde(with = "hex_eui")]
+    pub app_eui: u64,
+}
+
+/// Settings for a mirror sink. The sink type is selected with the `type` key.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MirrorSettings {
+    /// Forward uplinks as semtech UDP packets to the given address. The
+    /// gateway EUI reported in the packets is given as a hex string.
+    Udp {
+        addr: String,
+        #[serde(default, with = "hex_eui")]
+        gateway_eui: u64,
+    },
+    /// Post records as JSON to the given webhook uri
+    Http {
+        #[serde(with = "http_serde::uri")]
+        uri: Uri,
+    },
+    /// Append records as JSON lines to the given file
+    File { path: PathBuf },
+    /// Publish records to an MQTT broker at the given mqtt://host:port uri.
+    /// Records are published to "<topic>/<kind>" where kind is one of uplink,
+    /// downlink, beacon, witness or status. Downlinks published to
+    /// "<topic>/tx" are transmitted by the gateway.
+    Mqtt {
+        #[serde(with = "http_serde::uri")]
+        uri: Uri,
+        #[serde(default = "default_mqtt_topic")]
+        topic: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+/// Policy to select one of several downlinks for the same uplink.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum DownlinkPolicy {
+    /// Transmit the first downlink received for an uplink
+    FirstResponder,
+    /// Transmit the downlink of the OUI listed first in the given list. OUIs
+    /// that are not listed have the lowest priority.
+    OuiPriority { ouis: Vec<u32> },
+}
+
+impl Default for DownlinkPolicy {
+    fn default() -> Self {
+        Self::FirstResponder
+    }
+}
+
+/// Settings for passive roaming.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoamingSettings {
+    /// The NetID reported as the sender of roaming requests. Defaults to the
+    /// Helium NetID.
+    #[serde(default = "default_roaming_sender_id")]
+    pub sender_id: u32,
+    /// NetIDs of devaddrs that are routed through the Helium network and are
+    /// never roamed. Defaults to the Helium NetIDs.
+    #[serde(default = "default_roaming_local_netids")]
+    pub local_netids: Vec<u32>,
+    /// Roaming partners to forward foreign packets to
+    #[serde(default)]
+    pub partners: Vec<RoamingPartnerSettings>,
+}
+
+/// A roaming partner network.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoamingPartnerSettings {
+    /// The NetID of the partner network. Data packets with a devaddr in this
+    /// NetID are forwarded to the partner.
+    pub netid: u32,
+    /// The LoRaWAN Backend Interfaces HTTP endpoint of the partner
+    #[serde(with = "http_serde::uri")]
+    pub uri: Uri,
+    /// Join EUI ranges of the partner. Join requests with an app EUI in one of
+    /// these ranges are forwarded to the partner.
+    #[serde(default)]
+    pub join_euis: Vec<EuiRangeSettings>,
+}
+
+/// An inclusive range of EUIs given as hex strings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct EuiRangeSettings {
+    #[serde(with = "hex_eui")]
+    pub start: u64,
+    #[serde(with = "hex_eui")]
+    pub end: u64,
+}
+
 /// Settings for proof-of-coverage (PoC).
 #[derive(Debug, Deserialize, Clone)]
 pub struct PocSettings {
@@ -153,6 +468,26 @@ fn default_api() -> u16 {
     4467
 }
 
+fn default_api_socket_mode() -> u32 {
+    0o600
+}
+
+fn default_validator_override() -> bool {
+    true
+}
+
+fn default_roaming_sender_id() -> u32 {
+    0xC00053
+}
+
+fn default_roaming_local_netids() -> Vec<u32> {
+    vec![0x000024, 0x60002D, 0xC00053]
+}
+
+fn default_mqtt_topic() -> String {
+    "helium_gateway".to_string()
+}
+
 fn default_beacon_interval() -> u64 {
     // 3x daily with a few seconds to spare.
     8 * (3600 - 1)
@@ -208,9 +543,79 @@ impl FromStr for StakingMode {
     }
 }
 
+pub mod hex_eui {
+    use serde::de::{self, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(d: D) -> std::result::Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(d)?;
+        let hex = s.trim_start_matches("0x");
+        u64::from_str_radix(hex, 16).map_err(|_| de::Error::custom(format!("invalid eui \"{s}\"")))
+    }
+}
+
+pub mod static_route_oui {
+    use serde::de::{self, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(d: D) -> std::result::Result<u32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match u32::deserialize(d)? {
+            0 => Err(de::Error::custom("static route oui 0 is reserved")),
+            oui => Ok(oui),
+        }
+    }
+}
+
 pub mod log_level {
     use serde::de::{self, Deserialize, Deserializer, Visitor};
-    use std::fmt;
+    use std::{
+        fmt,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// The log level of the running service, which changes when settings are
+    /// reloaded. Defaults to info.
+    static RUNTIME_LEVEL: AtomicUsize = AtomicUsize::new(4);
+
+    pub fn set_runtime_level(level: Level) {
+        RUNTIME_LEVEL.store(level.0.as_usize(), Ordering::Relaxed);
+    }
+
+    fn runtime_level() -> slog::Level {
+        slog::Level::from_usize(RUNTIME_LEVEL.load(Ordering::Relaxed)).unwrap_or(slog::Level::Info)
+    }
+
+    /// A drain that only passes records at or above the runtime log level
+    #[derive(Debug)]
+    pub struct RuntimeLevelFilter<D>(D);
+
+    impl<D> RuntimeLevelFilter<D> {
+        pub fn new(drain: D, level: Level) -> Self {
+            set_runtime_level(level);
+            Self(drain)
+        }
+    }
+
+    impl<D: slog::Drain> slog::Drain for RuntimeLevelFilter<D> {
+        type Ok = Option<D::Ok>;
+        type Err = D::Err;
+
+        fn log(
+            &self,
+            record: &slog::Record,
+            values: &slog::OwnedKVList,
+        ) -> std::result::Result<Self::Ok, Self::Err> {
+            if record.level().is_at_least(runtime_level()) {
+                self.0.log(record, values).map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+    }
 
     #[derive(Debug, Clone, Copy)]
     pub struct Level(slog::Level);
@@ -269,7 +674,7 @@ pub mod log_method {
     use std::fmt;
 
     /// The method to use for logging.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub enum LogMethod {
         /// Display logging information on stdout
         Stdio,
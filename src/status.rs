@@ -0,0 +1,67 @@
+//! Snapshots of the state of the long running tasks. Each task publishes its
+//! status over a watch channel, which the local API reads to answer status
+//! requests.
+
+use crate::{KeyedUri, Region, Result};
+use std::time::SystemTime;
+use tokio::sync::watch;
+
+/// The outcome of the last attempt at something, like submitting a PoC report
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub time: SystemTime,
+    pub error: Option<String>,
+}
+
+impl<T> From<&Result<T>> for Attempt {
+    fn from(result: &Result<T>) -> Self {
+        Self {
+            time: SystemTime::now(),
+            error: result.as_ref().err().map(|err| format!("{err:?}")),
+        }
+    }
+}
+
+/// Status of the packet forwarder connection and the radio parameters
+#[derive(Debug, Clone, Default)]
+pub struct GatewayStatus {
+    pub connected: bool,
+    /// Mac of the last connected packet forwarder
+    pub mac: Option<String>,
+    /// The last time any packet or stat was received from the packet
+    /// forwarder
+    pub last_seen: Option<SystemTime>,
+    pub region: Option<Region>,
+    pub tx_power: Option<u32>,
+}
+
+/// Status of the validator connection
+#[derive(Debug, Clone, Default)]
+pub struct DispatcherStatus {
+    pub validator: Option<KeyedUri>,
+    pub height: u64,
+    pub block_age: u64,
+}
+
+/// Status of proof-of-coverage reporting
+#[derive(Debug, Clone, Default)]
+pub struct BeaconerStatus {
+    pub last_beacon: Option<Attempt>,
+    pub last_witness: Option<Attempt>,
+}
+
+/// Status of the auto-updater
+#[derive(Debug, Clone, Default)]
+pub struct UpdaterStatus {
+    pub enabled: bool,
+    pub last_check: Option<Attempt>,
+}
+
+/// Receivers for the status published by each of the tasks
+#[derive(Debug, Clone)]
+pub struct StatusReceivers {
+    pub gateway: watch::Receiver<GatewayStatus>,
+    pub dispatcher: watch::Receiver<DispatcherStatus>,
+    pub beaconer: watch::Receiver<BeaconerStatus>,
+    pub updater: watch::Receiver<UpdaterStatus>,
+}
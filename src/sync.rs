@@ -16,6 +16,10 @@ impl<T> MessageReceiver<T> {
     pub async fn recv(&mut self) -> Option<T> {
         self.0.recv().await
     }
+
+    pub fn close(&mut self) {
+        self.0.close()
+    }
 }
 
 impl<T> Clone for MessageSender<T> {
@@ -1,6 +1,10 @@
 pub mod releases;
 
-use crate::{settings, Result, Settings};
+use crate::{
+    settings::{self, ProxySettings, UpdateSettings},
+    status::{Attempt, UpdaterStatus},
+    sync, Result, Settings,
+};
 use futures::TryStreamExt;
 use http::Uri;
 use releases::Channel;
@@ -9,7 +13,29 @@ use std::{
     env, io,
     path::{Path, PathBuf},
 };
-use tokio::{process, time};
+use tokio::{process, sync::watch, time};
+
+#[derive(Debug)]
+pub enum Message {
+    SettingsChanged(UpdateSettings),
+}
+
+pub type MessageSender = sync::MessageSender<Message>;
+pub type MessageReceiver = sync::MessageReceiver<Message>;
+
+pub fn message_channel(size: usize) -> (MessageSender, MessageReceiver) {
+    sync::message_channel(size)
+}
+
+impl MessageSender {
+    /// Applies the update settings of reloaded settings
+    pub async fn settings_changed(&self, settings: &Settings) {
+        let _ = self
+            .0
+            .send(Message::SettingsChanged(settings.update.clone()))
+            .await;
+    }
+}
 
 #[derive(Debug)]
 pub struct Updater {
@@ -19,49 +45,77 @@ pub struct Updater {
     platform: String,
     interval: time::Duration,
     install_command: String,
+    proxy: Option<ProxySettings>,
+    messages: MessageReceiver,
+    status: watch::Sender<UpdaterStatus>,
 }
 
 impl Updater {
-    pub fn new(settings: &Settings) -> Result<Self> {
+    pub fn new(settings: &Settings, messages: MessageReceiver) -> Result<Self> {
         Ok(Self {
             enabled: settings.update.enabled,
             channel: settings.update.channel.clone(),
             platform: settings.update.platform.clone(),
-            interval: time::Duration::from_secs(settings.update.interval as u64 * 60),
+            interval: interval(&settings.update),
             uri: settings.update.uri.clone(),
             install_command: settings.update.command.clone(),
+            proxy: settings.proxy.clone(),
+            messages,
+            status: watch::channel(UpdaterStatus {
+                enabled: settings.update.enabled,
+                last_check: None,
+            })
+            .0,
         })
     }
 
-    pub async fn run(&self, shutdown: triggered::Listener, logger: &Logger) -> Result {
+    /// Returns a receiver for the result of the last update check
+    pub fn status(&self) -> watch::Receiver<UpdaterStatus> {
+        self.status.subscribe()
+    }
+
+    /// Runs the update checks. A disabled updater keeps running so that
+    /// reloaded settings can enable it.
+    pub async fn run(&mut self, shutdown: triggered::Listener, logger: &Logger) -> Result {
         let logger = logger.new(o!("module" => "updater"));
-        if !self.enabled {
+        if self.enabled {
+            info!(logger, "starting");
+        } else {
             info!(logger, "disabling");
-            return Ok(());
         }
-        info!(logger, "starting");
-        let mut interval = time::interval(self.interval);
+        let mut check_timer = time::interval(self.interval);
         loop {
             tokio::select! {
                 _ = shutdown.clone() => {
                     info!(logger, "shutting down");
                     return Ok(())
                 },
-                _ = interval.tick() => {
+                message = self.messages.recv() => match message {
+                    Some(Message::SettingsChanged(settings)) => {
+                        if interval(&settings) != self.interval {
+                            check_timer = time::interval(interval(&settings));
+                        }
+                        self.update_settings(settings, &logger);
+                    },
+                    None => warn!(logger, "ignoring closed message channel"),
+                },
+                _ = check_timer.tick(), if self.enabled => {
                     // Get the current version and find the first release
                     // version in the settings channel that is newer than the
                     // package version.
                     let current_version = settings::version();
                     let channel = self.channel.clone();
                     let platform = self.platform.clone();
-                    match releases::filtered(releases::all(self.uri.to_string()), move | r | {
+                    let release = releases::filtered(releases::all(self.uri.to_string(), self.proxy.clone()), move | r | {
                         r.in_channel(&channel) && r.version > current_version && r.asset_for_platform(&platform).is_some()
-                    }).try_next().await {
+                    }).try_next().await;
+                    self.status.send_modify(|status| status.last_check = Some(Attempt::from(&release)));
+                    match release {
                         Ok(Some(release)) => {
                             let asset = release.asset_for_platform(&self.platform).expect("asset for platform");
                             info!(logger, "downloading {asset}", asset = asset.name.clone());
                             let download_path = self.download_path(&asset.name);
-                            asset.download(&download_path).await?;
+                            asset.download(&download_path, self.proxy.as_ref()).await?;
                             info!(logger, "installing {asset}", asset=asset.name.clone());
                             return self.install(&download_path, &logger).await;
                         },
@@ -73,6 +127,20 @@ impl Updater {
         }
     }
 
+    fn update_settings(&mut self, settings: UpdateSettings, logger: &Logger) {
+        self.interval = interval(&settings);
+        self.enabled = settings.enabled;
+        self.channel = settings.channel;
+        self.platform = settings.platform;
+        self.uri = settings.uri;
+        self.install_command = settings.command;
+        self.status
+            .send_modify(|status| status.enabled = self.enabled);
+        info!(logger, "updated settings";
+            "enabled" => self.enabled,
+            "interval" => self.interval.as_secs());
+    }
+
     /// Returns a temporary location to download a package into. Do _not_ return a
     /// path that will be used for an actual update since a partial download may
     /// remain after download failures.
@@ -102,3 +170,7 @@ impl Updater {
         }
     }
 }
+
+fn interval(settings: &UpdateSettings) -> time::Duration {
+    time::Duration::from_secs(settings.interval as u64 * 60)
+}
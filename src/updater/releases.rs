@@ -1,4 +1,8 @@
-use crate::{curl, releases, settings, Error, Future, Result, Stream};
+use crate::{
+    curl, proxy, releases,
+    settings::{self, ProxySettings},
+    Error, Future, Result, Stream,
+};
 use futures::{future, stream, FutureExt, StreamExt, TryFutureExt};
 use semver::{Identifier, Version};
 use serde::{de, Deserialize, Deserializer};
@@ -17,35 +21,41 @@ where
         .boxed()
 }
 
-/// Get a stream of all releases
-pub fn all(url: String) -> Stream<Release> {
-    fetch_releases(url, 1)
+/// Get a stream of all releases, fetched through the given proxy if any
+pub fn all(url: String, proxy: Option<ProxySettings>) -> Stream<Release> {
+    fetch_releases(url, 1, proxy.clone())
         .map_ok(move |((url, page), items)| {
-            stream::try_unfold(
-                ((url, page), items),
-                |((url, page), mut items)| async move {
+            stream::try_unfold(((url, page), items), move |((url, page), mut items)| {
+                let proxy = proxy.clone();
+                async move {
                     match items.pop() {
                         Some(item) => Ok(Some((item, ((url, page), items)))),
                         None => {
-                            let ((url, page), mut items) = fetch_releases(url, page + 1).await?;
+                            let ((url, page), mut items) =
+                                fetch_releases(url, page + 1, proxy).await?;
                             match items.pop() {
                                 Some(item) => Ok(Some((item, ((url, page), items)))),
                                 None => Ok(None),
                             }
                         }
                     }
-                },
-            )
+                }
+            })
         })
         .try_flatten_stream()
         .boxed()
 }
 
-fn fetch_releases(url: String, page: u32) -> Future<((String, u32), Vec<Release>)> {
+fn fetch_releases(
+    url: String,
+    page: u32,
+    proxy: Option<ProxySettings>,
+) -> Future<((String, u32), Vec<Release>)> {
     let curl_url = format!("{url}?per_page={GH_PAGE_SIZE}&page={page}");
     curl::get(
         curl_url,
         ["-H", "Accept: application/vnd.github.v3+json"],
+        proxy.as_ref(),
         move |output| {
             let mut items: Vec<Release> = serde_json::from_slice(output)?;
             items.reverse();
@@ -224,8 +234,13 @@ pub struct ReleaseAsset {
 
 impl ReleaseAsset {
     /// Downloads the asset to a given destination.
-    pub async fn download(&self, dest: &Path) -> Result {
-        process::Command::new("curl")
+    /// Downloads the asset to the given path, through the given proxy if any
+    pub async fn download(&self, dest: &Path, proxy: Option<&ProxySettings>) -> Result {
+        let mut command = process::Command::new("curl");
+        if let Some(proxy) = proxy {
+            command.arg("--proxy").arg(proxy::curl_uri(proxy));
+        }
+        command
             .kill_on_drop(true)
             .arg("-s")
             .arg("-L")